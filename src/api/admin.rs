@@ -0,0 +1,486 @@
+/*!
+ * Administration of the records by the operators, protected by the `admin_token`
+ */
+use futures::StreamExt;
+use redis::AsyncCommands;
+use rocket::{
+    delete, get,
+    http::Status,
+    post,
+    request::{self, FromRequest, Request},
+    serde::json::Json,
+    FromForm, State,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use crate::{
+    config::Config,
+    types::{
+        self, Blocklist, Pool, PooledConnection, Reason, Record, RecordData, RecordType, Tombstone,
+        Topology,
+    },
+    Error, Result,
+};
+
+/** Default number of records returned by a search */
+const DEFAULT_LIMIT: usize = 100;
+
+/** Ensure the request bears the configured admin token */
+pub struct Admin;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = Error<'static>;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let expected = match req
+            .rocket()
+            .state::<Config>()
+            .and_then(|config| config.admin_token.as_ref())
+        {
+            Some(token) => Sha256::digest(token.as_bytes()),
+            None => {
+                return request::Outcome::Failure((
+                    Status::NotFound,
+                    Error::NotFound(String::from("admin")),
+                ))
+            }
+        };
+
+        /* Compare digests in constant time, so the comparison does not leak the token's length or prefix */
+        match req
+            .headers()
+            .get_one("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+        {
+            Some(token) if bool::from(Sha256::digest(token.trim().as_bytes()).ct_eq(&expected)) => {
+                request::Outcome::Success(Admin)
+            }
+            _ => request::Outcome::Failure((
+                Status::Unauthorized,
+                Error::Unauthorized("a valid admin token is required".into()),
+            )),
+        }
+    }
+}
+
+#[derive(FromForm)]
+pub struct Search {
+    /** Type of the records */
+    #[field(name = "type")]
+    kind: Option<RecordType>,
+    /** Minimum size of the records, in bytes */
+    min_size: Option<usize>,
+    /** Maximum size of the records, in bytes */
+    max_size: Option<usize>,
+    /** Only the records expiring before this timestamp */
+    expires_before: Option<i64>,
+    /** Only the records expiring after this timestamp */
+    expires_after: Option<i64>,
+    /** Only the records of this owner */
+    owner: Option<String>,
+    /** Maximum number of records returned */
+    limit: Option<usize>,
+}
+
+impl Search {
+    /** Whether the `record` matches the [`Search`]'s filters */
+    fn matches(&self, record: &Record) -> bool {
+        let size = record.data().size();
+        let expiry = record.expiry().map(|expiry| expiry.timestamp());
+
+        self.kind.is_none_or(|kind| record.data().kind() == kind)
+            && self.min_size.is_none_or(|min| size >= min)
+            && self.max_size.is_none_or(|max| size <= max)
+            && self
+                .expires_before
+                .is_none_or(|before| expiry.is_some_and(|expiry| expiry < before))
+            && self
+                .expires_after
+                .is_none_or(|after| expiry.is_none_or(|expiry| expiry > after))
+            && self
+                .owner
+                .as_deref()
+                .is_none_or(|owner| record.owner() == Some(owner))
+    }
+}
+
+/** The decoded [`Record`], as shown to the operators */
+#[derive(Serialize)]
+pub struct RecordView {
+    slug: String,
+    #[serde(rename = "type")]
+    kind: RecordType,
+    /** Size of the record's data, in bytes */
+    size: usize,
+    /** Expiry timestamp of the record, none if it's pinned */
+    expiry: Option<i64>,
+    /** Remaining number of accesses, if applicable */
+    accesses: Option<u16>,
+    burn: bool,
+    owner: Option<String>,
+    /** The file's name, the url's target or the paste's first characters */
+    summary: String,
+}
+
+impl From<&Record> for RecordView {
+    fn from(record: &Record) -> Self {
+        /* Only a glimpse of the pastes is shown, they are accessible through their slug anyway */
+        const PASTE_PREVIEW: usize = 128;
+
+        RecordView {
+            slug: record.slug().to_string(),
+            kind: record.data().kind(),
+            size: record.data().size(),
+            expiry: record.expiry().map(|expiry| expiry.timestamp()),
+            accesses: record.accesses(),
+            burn: record.is_burning(),
+            owner: record.owner().map(String::from),
+            summary: match record.data() {
+                RecordData::File { name, .. } => name.clone(),
+                RecordData::Url { target, .. } => target.to_string(),
+                RecordData::Paste { body } => body.chars().take(PASTE_PREVIEW).collect(),
+            },
+        }
+    }
+}
+
+#[get("/admin/records?<search..>")]
+pub async fn search(
+    search: Search,
+    admin: Result<Admin, Error<'static>>,
+    topology: &State<Topology>,
+    redis: &State<Pool>,
+) -> Result<Json<Vec<RecordView>>> {
+    admin?;
+
+    let mut conn = redis.get();
+    let limit = search.limit.unwrap_or(DEFAULT_LIMIT);
+
+    /* Keys are local to each node, so we scan every master */
+    let mut views = Vec::new();
+    'nodes: for node in topology.masters(&mut conn).await? {
+        let mut node_conn = node.get_async_connection().await?;
+        let mut keys = node_conn
+            .scan_match::<_, String>([types::STORAGE_PREFIX, "*"].concat())
+            .await?;
+
+        /* The keys are walked lazily, so the scan stops along with the search */
+        while let Some(key) = keys.next().await {
+            let slug = &key[types::STORAGE_PREFIX.len()..];
+
+            match Record::fetch(slug, &mut conn).await? {
+                Some(record) if search.matches(&record) => views.push(RecordView::from(&record)),
+                _ => continue,
+            }
+
+            if views.len() >= limit {
+                break 'nodes;
+            }
+        }
+    }
+
+    Ok(Json(views))
+}
+
+#[get("/admin/records/<slug>")]
+pub async fn inspect(
+    slug: String,
+    admin: Result<Admin, Error<'static>>,
+    redis: &State<Pool>,
+) -> Result<Json<RecordView>> {
+    admin?;
+
+    let mut conn = redis.get();
+    let record = Record::find(&slug, &mut conn).await?;
+
+    Ok(Json(RecordView::from(&record)))
+}
+
+#[derive(Deserialize)]
+pub struct Takedown {
+    /** Explanation of the takedown, shown to the visitors */
+    reason: String,
+}
+
+/** Forcefully remove the `record`, leaving the `tombstone` in it's place */
+async fn remove(
+    record: Record,
+    tombstone: Tombstone,
+    config: &Config,
+    conn: &mut PooledConnection,
+) -> Result<()> {
+    let slug = record.slug().to_string();
+
+    record.delete(&mut *conn).await?;
+    /* The takedowns are meant to stick, so they are kept for their own lifetime */
+    let lifetime = match tombstone.reason() {
+        Reason::TakenDown => config.takedown_lifetime,
+        _ => Some(config.tombstone_lifetime),
+    };
+    tombstone.bury(&slug, lifetime, &mut *conn).await?;
+    config
+        .webhooks()
+        .deleted(&slug, tombstone.reason(), &mut *conn)
+        .await?;
+    config.quota().release(&slug, conn).await
+}
+
+#[delete("/admin/records/<slug>")]
+pub async fn purge(
+    slug: String,
+    admin: Result<Admin, Error<'static>>,
+    config: &State<Config>,
+    redis: &State<Pool>,
+) -> Result<Status> {
+    admin?;
+
+    let mut conn = redis.get();
+    let record = Record::find(&slug, &mut conn).await?;
+
+    tracing::warn!("Purging {:?} on behalf of the operators", record);
+
+    remove(record, Tombstone::new(Reason::Purged), config, &mut conn).await?;
+
+    Ok(Status::NoContent)
+}
+
+#[post("/admin/records/<slug>/takedown", data = "<takedown>")]
+pub async fn takedown(
+    slug: String,
+    takedown: Json<Takedown>,
+    admin: Result<Admin, Error<'static>>,
+    config: &State<Config>,
+    redis: &State<Pool>,
+) -> Result<Status> {
+    admin?;
+
+    let mut conn = redis.get();
+    let record = Record::find(&slug, &mut conn).await?;
+
+    tracing::warn!(
+        "Taking down {:?} on behalf of the operators: {}",
+        record,
+        takedown.reason
+    );
+
+    /* Block the content itself, so it can't come back under another slug */
+    let hash = match record.data() {
+        RecordData::File { path, .. } => Some(Blocklist::hash_file(path).await?),
+        RecordData::Paste { body } => Some(Blocklist::hash(body.as_bytes())),
+        RecordData::Url { .. } => None,
+    };
+    if let Some(hash) = hash {
+        Blocklist::Hashes.add(&[hash], &mut conn).await?;
+    }
+
+    let tombstone = Tombstone::new(Reason::TakenDown).with_details(takedown.into_inner().reason);
+    remove(record, tombstone, config, &mut conn).await?;
+
+    Ok(Status::NoContent)
+}
+
+#[get("/admin/blocklists/<list>")]
+pub async fn blocklist(
+    list: Blocklist,
+    admin: Result<Admin, Error<'static>>,
+    redis: &State<Pool>,
+) -> Result<Json<Vec<String>>> {
+    admin?;
+
+    let mut conn = redis.get();
+
+    Ok(Json(list.entries(&mut conn).await?))
+}
+
+#[post("/admin/blocklists/<list>", data = "<entries>")]
+pub async fn block(
+    list: Blocklist,
+    entries: Json<Vec<String>>,
+    admin: Result<Admin, Error<'static>>,
+    redis: &State<Pool>,
+) -> Result<Json<Vec<String>>> {
+    admin?;
+
+    let mut conn = redis.get();
+    list.add(&entries, &mut conn).await?;
+
+    Ok(Json(list.entries(&mut conn).await?))
+}
+
+#[delete("/admin/blocklists/<list>", data = "<entries>")]
+pub async fn unblock(
+    list: Blocklist,
+    entries: Json<Vec<String>>,
+    admin: Result<Admin, Error<'static>>,
+    redis: &State<Pool>,
+) -> Result<Json<Vec<String>>> {
+    admin?;
+
+    let mut conn = redis.get();
+    list.remove(&entries, &mut conn).await?;
+
+    Ok(Json(list.entries(&mut conn).await?))
+}
+
+#[post("/admin/records/<slug>/pin")]
+pub async fn pin(
+    slug: String,
+    admin: Result<Admin, Error<'static>>,
+    redis: &State<Pool>,
+) -> Result<Json<RecordView>> {
+    admin?;
+
+    let mut conn = redis.get();
+    let record = Record::find(&slug, &mut conn).await?.pin(&mut conn).await?;
+
+    tracing::info!("Pinned {:?} on behalf of the operators", record);
+
+    Ok(Json(RecordView::from(&record)))
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::{
+        http::Header,
+        local::asynchronous::Client,
+        serde::json::{self, Value},
+    };
+
+    use super::*;
+    use crate::types::{KeyPolicy, STORAGE_PREFIX, TOMBSTONE_PREFIX};
+
+    async fn client() -> Client {
+        let config = Config {
+            admin_token: Some(String::from("secret")),
+            anonymous: KeyPolicy::default(),
+            ..Config::default()
+        };
+
+        crate::api::client(config).await.0
+    }
+
+    async fn paste(client: &Client, slug: &str, body: &'static str) -> Status {
+        client
+            .post("/paste")
+            .remote("203.0.113.7:4711".parse().unwrap())
+            .header(Header::new("Host", "shrekd.example"))
+            .header(Header::new("Custom-Slug", slug.to_string()))
+            .header(Header::new("Slug-Strict", "true"))
+            .body(body)
+            .dispatch()
+            .await
+            .status()
+    }
+
+    #[rocket::async_test]
+    async fn admin_routes_require_the_token() {
+        let client = client().await;
+
+        for (authorization, expected) in [
+            (None, Status::Unauthorized),
+            (Some("Bearer wrong"), Status::Unauthorized),
+            (Some("secret"), Status::Unauthorized),
+            (Some("Bearer secret"), Status::Ok),
+        ] {
+            let mut request = client.get("/admin/blocklists/hashes");
+            if let Some(authorization) = authorization {
+                request = request.header(Header::new("Authorization", authorization));
+            }
+
+            assert_eq!(request.dispatch().await.status(), expected);
+        }
+    }
+
+    #[rocket::async_test]
+    async fn admin_routes_are_disabled_without_a_token() {
+        let (client, _) = crate::api::client(Config::default()).await;
+
+        let response = client
+            .get("/admin/blocklists/hashes")
+            .header(Header::new("Authorization", "Bearer "))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[rocket::async_test]
+    async fn takedowns_are_permanent_and_block_the_content() {
+        let client = client().await;
+        assert_eq!(paste(&client, "vanity", "forbidden").await, Status::Created);
+
+        let response = client
+            .post("/admin/records/vanity/takedown")
+            .header(Header::new("Authorization", "Bearer secret"))
+            .body(r#"{"reason": "copyright"}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NoContent);
+
+        let response = client.get("/vanity").dispatch().await;
+        assert_eq!(response.status(), Status::UnavailableForLegalReasons);
+
+        /* The tombstone never expires, and neither the slug nor the content can come back */
+        let mut conn = client.rocket().state::<Pool>().unwrap().get();
+        let ttl: i64 = conn
+            .ttl([TOMBSTONE_PREFIX, "vanity"].concat())
+            .await
+            .unwrap();
+        assert_eq!(ttl, -1);
+        assert_eq!(paste(&client, "vanity", "other").await, Status::Conflict);
+        assert_eq!(
+            paste(&client, "other", "forbidden").await,
+            Status::UnavailableForLegalReasons
+        );
+    }
+
+    #[rocket::async_test]
+    async fn records_are_inspected_pinned_and_purged() {
+        let client = client().await;
+        assert_eq!(paste(&client, "vanity", "content").await, Status::Created);
+        let admin = || Header::new("Authorization", "Bearer secret");
+
+        let response = client
+            .get("/admin/records/vanity")
+            .header(admin())
+            .dispatch()
+            .await;
+        let view: Value = json::from_str(&response.into_string().await.unwrap()).unwrap();
+        assert_eq!(view["type"], "paste");
+        assert_eq!(view["size"], 7);
+        assert!(view["expiry"].is_i64());
+
+        /* Pinned records lose their expiry, in Redis as well */
+        let response = client
+            .post("/admin/records/vanity/pin")
+            .header(admin())
+            .dispatch()
+            .await;
+        let view: Value = json::from_str(&response.into_string().await.unwrap()).unwrap();
+        assert!(view["expiry"].is_null());
+        let mut conn = client.rocket().state::<Pool>().unwrap().get();
+        let ttl: i64 = conn.ttl([STORAGE_PREFIX, "vanity"].concat()).await.unwrap();
+        assert_eq!(ttl, -1);
+
+        let response = client
+            .delete("/admin/records/vanity")
+            .header(admin())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NoContent);
+
+        let tombstone = Tombstone::fetch("vanity", &mut conn)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(tombstone.reason(), Reason::Purged);
+        let response = client
+            .get("/admin/records/vanity")
+            .header(admin())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Gone);
+    }
+}
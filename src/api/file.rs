@@ -1,50 +1,116 @@
-use rocket::{fs::TempFile, http::Header, put, response::Responder, uri, State};
+use chrono::{DateTime, Utc};
+use rocket::{data::ToByteUnit, fs::TempFile, http::Header, put, response::Responder, State};
 use tokio::fs;
 
 use super::CreatedResponse;
 use crate::{
     config::Config,
-    types::{HostBase, Record, RecordSettings},
+    types::{Blocklist, HostBase, Identity, Pool, Record, RecordSettings, RecordType, SlugGrammar},
     Error, Result,
 };
 
+#[allow(clippy::too_many_arguments)] /* <- Every guard is an argument of the handler */
 #[put("/<filename>", data = "<file>")]
 pub async fn create<'r>(
     filename: String,
     file: Result<TempFile<'_>, std::io::Error>,
-    host: HostBase<'_>,
+    identity: Result<Identity, Error<'static>>,
+    host: Result<HostBase<'_>, Error<'static>>,
     settings: RecordSettings,
+    grammar: &State<SlugGrammar>,
     config: &State<Config>,
-    redis: &State<redis::Client>,
+    redis: &State<Pool>,
 ) -> Result<impl Responder<'r, 'static>> {
+    let identity = identity?;
+    let host = host?;
+    identity.authorize(RecordType::File, &settings)?;
+
     let mut file = file.map_err(|err| Error::FileUpload(err.to_string()))?;
 
-    let mut conn = redis.get_async_connection().await?;
-
-    /* Compute the slug and the appropriate storage path from it */
-    let slug = settings.slug(config, &mut conn).await?;
-    let storage = fs::canonicalize(&config.data_dir).await?.join(&slug);
-    let size = file.len();
-
-    /* Compute the Record's max age from it's size */
-    let max_age = config.curve()?.compute_for(size);
-    let expiry = settings.expiry(Some(max_age)).unwrap(); // <- unwrap here is safe, because the Option conditioned by the `max_age` parameter
-
-    /* Instanciate a new record from it */
-    let record = Record::file(
-        filename.to_string(),
-        storage.clone(),
-        size as usize,
-        slug,
-        settings.accesses(),
-        Some(expiry),
-    );
+    /* The server's own limit is enforced while streaming, but the key's may be lower */
+    let max_file_size = identity.max_file_size(config.max_file_size);
+    if file.len() > max_file_size {
+        return Err(Error::PayloadTooLarge(format!(
+            "the file of {} exceeds the maximum file size of {}",
+            file.len().bytes(),
+            max_file_size.bytes()
+        )));
+    }
+
+    let mut conn = redis.get();
+
+    /* Ensure the client's rolling quotas allow for this file */
+    let throttle = identity.throttle();
+    let charge = throttle.check(file.len(), &mut conn).await?;
+
+    let mut slug = None;
+    let persisted: Result<(Record, DateTime<Utc>)> = async {
+        /* Compute the slug and the appropriate storage path from it */
+        let slug = slug.insert(settings.slug(grammar, config, None, &mut conn).await?);
+        let storage = fs::canonicalize(&config.data_dir).await?.join(&slug);
+        let size = file.len();
+
+        /* Compute the Record's max age from it's size */
+        let max_age = identity.max_age(config.curve()?.compute_for(size));
+        let expiry = settings.expiry(Some(max_age)).unwrap(); // <- unwrap here is safe, because the Option conditioned by the `max_age` parameter
+
+        /* Instanciate a new record from it */
+        let record = Record::file(
+            filename.to_string(),
+            storage.clone(),
+            size as usize,
+            slug.clone(),
+            settings.accesses(),
+            Some(expiry),
+        )
+        .burn_after_reading(settings.burn())
+        .require_signature(settings.signed())
+        .owned_by(identity.name());
+
+        tracing::debug!("Received a file upload {:?}", record);
+
+        /* Reserve the space needed by the file in the storage quota, last since it's given back on any failure */
+        let quota = config.quota();
+        quota.reserve(size, &mut conn).await?;
+        if let Err(err) = quota.mark(record.slug(), size, &mut conn).await {
+            quota.unreserve(size, &mut conn).await?;
+
+            return Err(err);
+        }
+
+        /* Finally try to persist this file, ensure it's content is not blocked, and push the record */
+        let stored: Result<()> = async {
+            file.persist_to(&storage).await?;
+            Blocklist::check_file(&storage, &mut conn).await?;
+            record.persist(&mut conn).await
+        }
+        .await;
+        if let Err(err) = stored {
+            /* Remove whatever was written and give back the reservation */
+            quota.release(record.slug(), &mut conn).await?;
+
+            return Err(err);
+        }
+
+        Ok((record, expiry))
+    }
+    .await;
 
-    tracing::debug!("Received a file upload {:?}", record);
+    /* Give back the slug whatever happened, since it's either held by the record by now or free again */
+    if let Some(slug) = &slug {
+        Record::unclaim(slug, &mut conn).await?;
+    }
+    /* Only the created records count in the rolling quotas */
+    if persisted.is_err() {
+        throttle.refund(&charge, &mut conn).await?;
+    }
+    let (record, expiry) = persisted?;
 
-    /* Finally try to persist this file, and push the record */
-    file.persist_to(storage).await?;
-    record.persist(&mut conn).await?;
+    throttle.track(&record, &mut conn).await?;
+    config
+        .webhooks()
+        .created(&record, settings.notify(), &mut conn)
+        .await?;
 
     tracing::debug!(
         "Successfully persisted the file with the slug `{}`",
@@ -52,8 +118,8 @@ pub async fn create<'r>(
     );
 
     Ok(CreatedResponse(
-        host.with(uri!(super::get::get(slug = record.slug())))
-            .to_string(),
+        super::get::link(&record, &host, config),
         Header::new("Expiry", expiry.timestamp().to_string()),
+        charge.limit(),
     ))
 }
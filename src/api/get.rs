@@ -1,47 +1,322 @@
-use rocket::{get, http::Header, response::Responder, State};
+use chrono::Utc;
+use rocket::{
+    get,
+    http::Header,
+    post,
+    response::{content, Responder},
+    uri, State,
+};
 use tokio::fs;
 
-use crate::types::{Record, RecordData};
+use crate::{
+    config::Config,
+    types::{
+        Analytics, HostBase, Pool, PooledConnection, Reason, Record, RecordData, RedirectStatus,
+        Tombstone, UserAgent, Visit,
+    },
+    Error, Result,
+};
+
+/** Max age of the permanent redirects of pinned records, which never expire, in seconds */
+const PINNED_MAX_AGE: i64 = 60 * 60 * 24 * 365;
 
 #[derive(Debug, Responder)]
 pub enum RecordResponse {
     #[response(content_type = "binary")]
     File(rocket::tokio::fs::File, Header<'static>),
-    Url(rocket::response::Redirect),
+    Url(rocket::response::Redirect, Header<'static>),
     #[response(content_type = "text/plain;charset=utf-8")]
     Paste(String),
+    Confirmation(content::Html<String>),
 }
 
-#[get("/<slug>")]
-pub async fn get<'r>(
-    slug: String,
-    redis: &State<redis::Client>,
-) -> crate::Result<impl Responder<'r, 'static>> {
-    let mut conn = redis.get_async_connection().await?;
+impl RecordResponse {
+    /** Transform the record's data into the suited response */
+    async fn from(record: &Record) -> Result<Self> {
+        Ok(match record.data() {
+            RecordData::File { path, name, .. } => RecordResponse::File(
+                fs::File::open(path).await?,
+                Header::new(
+                    "Content-Disposition",
+                    format!("attachment; filename={}", name),
+                ),
+            ),
+            RecordData::Url { target, status } => RecordResponse::Url(
+                status.redirect(target.clone()),
+                Header::new("Cache-Control", Self::cache_control(record, *status)),
+            ),
+            RecordData::Paste { body } => RecordResponse::Paste(body.clone()),
+        })
+    }
 
-    let record = Record::fetch(&slug, &mut conn)
-        .await?
-        .ok_or(crate::Error::NotFound(slug))?;
+    /** Compute the `Cache-Control` of the redirect, so browsers never remember it past the record's lifetime */
+    fn cache_control(record: &Record, status: RedirectStatus) -> String {
+        /* Every access must reach us when it's counted, or when the link is only valid for a while */
+        if !status.is_permanent()
+            || record.accesses().is_some()
+            || record.is_burning()
+            || record.is_signed()
+        {
+            return String::from("no-store");
+        }
 
-    tracing::debug!("Found {:#?}", record);
+        let max_age = record
+            .expiry()
+            .map(|expiry| (expiry - Utc::now()).num_seconds().max(0))
+            .unwrap_or(PINNED_MAX_AGE);
 
-    /* Transform the record's data into the suited response */
-    let response = match record.data() {
-        RecordData::File { path, name, .. } => RecordResponse::File(
-            fs::File::open(path).await?,
-            Header::new(
-                "Content-Disposition",
-                format!("attachment; filename={}", name),
-            ),
-        ),
-        RecordData::Url { target } => {
-            RecordResponse::Url(rocket::response::Redirect::to(target.clone()))
+        format!("public, max-age={}", max_age)
+    }
+
+    /** Render the page asking for an explicit confirmation before revealing the burnt or counted record */
+    fn confirmation(record: &Record, signature: Option<(i64, &str)>) -> Result<Self> {
+        let template = liquid::ParserBuilder::with_stdlib()
+            .build()?
+            .parse(include_str!("../../ui/confirm.html"))?;
+
+        let globals = liquid::object!({
+            "slug": record.slug(),
+            "type": record.data().kind(),
+            "burn": record.is_burning(),
+            /* A single access left is already implied by burning */
+            "accesses": record.accesses().filter(|accesses| !record.is_burning() || *accesses > 1),
+            /* The reveal must carry the signature along */
+            "query": signature.map(|(exp, sig)| format!("?exp={}&sig={}", exp, sig)),
+        });
+
+        Ok(RecordResponse::Confirmation(content::Html(
+            template.render(&globals)?,
+        )))
+    }
+}
+
+/** Whether the link previews must be kept away from the [`Record`]'s content, since they never consume it */
+fn is_guarded(record: &Record) -> bool {
+    record.is_burning() || record.accesses().is_some()
+}
+
+/** Ensure the signed [`Record`]s are accessed through a valid link, bearing it's `exp` and `sig` */
+pub(super) fn verify(
+    record: &Record,
+    exp: Option<i64>,
+    sig: Option<&str>,
+    config: &Config,
+) -> Result<()> {
+    if !record.is_signed() {
+        return Ok(());
+    }
+
+    match config.signer() {
+        Some(signer) => signer.verify(record.slug(), exp, sig),
+        None => Err(Error::Forbidden(String::from(
+            "signed links are disabled on this server",
+        ))),
+    }
+}
+
+/** Render the link to the newly created `record`, signed for the default lifetime when it requires a signature */
+pub(super) fn link(record: &Record, host: &HostBase<'_>, config: &Config) -> String {
+    let signature = match (record.is_signed(), config.signer()) {
+        (true, Some(signer)) => {
+            let expiry = Utc::now().timestamp() + config.signed_link_lifetime as i64;
+
+            (Some(expiry), Some(signer.sign(record.slug(), expiry)))
         }
-        RecordData::Paste { body } => RecordResponse::Paste(body.clone()),
+        _ => (None, None),
     };
 
-    /* Consume the record to update it's access count if required */
-    record.consume(&mut conn).await?;
+    host.with(uri!(get(
+        slug = record.slug(),
+        exp = signature.0,
+        sig = signature.1
+    )))
+    .to_string()
+}
+
+#[get("/<slug>?<exp>&<sig>")]
+pub async fn get(
+    slug: String,
+    exp: Option<i64>,
+    sig: Option<String>,
+    agent: UserAgent,
+    visit: Visit,
+    config: &State<Config>,
+    redis: &State<Pool>,
+) -> Result<RecordResponse> {
+    let mut conn = redis.get();
+
+    let record = Record::find(&slug, &mut conn).await?;
+
+    tracing::debug!("Found {:#?}", record);
+
+    verify(&record, exp, sig.as_deref(), config)?;
+
+    /* Burnt records are only revealed by an explicit `POST`, and link previews only get to see the confirmation of counted ones */
+    if record.is_burning() || (agent.is_preview() && is_guarded(&record)) {
+        return RecordResponse::confirmation(&record, exp.zip(sig.as_deref()));
+    }
+
+    let response = RecordResponse::from(&record).await?;
+
+    /* Link previews must leave the record untouched, which is harmless since it's not counted */
+    if !agent.is_preview() {
+        consume(&slug, record, &visit, config, &mut conn).await?;
+    }
 
     Ok(response)
 }
+
+#[post("/<slug>?<exp>&<sig>")]
+pub async fn reveal(
+    slug: String,
+    exp: Option<i64>,
+    sig: Option<String>,
+    agent: UserAgent,
+    visit: Visit,
+    config: &State<Config>,
+    redis: &State<Pool>,
+) -> Result<RecordResponse> {
+    let mut conn = redis.get();
+
+    let record = Record::find(&slug, &mut conn).await?;
+
+    tracing::debug!("Revealing {:#?}", record);
+
+    verify(&record, exp, sig.as_deref(), config)?;
+
+    /* Link previews never get to see a burnt or counted record */
+    if agent.is_preview() && is_guarded(&record) {
+        return RecordResponse::confirmation(&record, exp.zip(sig.as_deref()));
+    }
+
+    let response = RecordResponse::from(&record).await?;
+
+    /* Link previews must leave the record untouched, which is harmless since it's not counted */
+    if !agent.is_preview() {
+        consume(&slug, record, &visit, config, &mut conn).await?;
+    }
+
+    Ok(response)
+}
+
+/** Consume the record to update it's access count if required, and leave a trace once exhausted */
+async fn consume(
+    slug: &str,
+    record: Record,
+    visit: &Visit,
+    config: &Config,
+    conn: &mut PooledConnection,
+) -> Result<()> {
+    /* Only the clicks on url redirects are tracked */
+    if let RecordData::Url { .. } = record.data() {
+        Analytics::click(&record, visit, &mut *conn).await?;
+    }
+
+    let accesses = record.accesses().map(|count| count - 1);
+    let exhausted = record.consume(&mut *conn).await?;
+
+    let webhooks = config.webhooks();
+    webhooks.accessed(slug, accesses, &mut *conn).await?;
+
+    if exhausted {
+        Tombstone::new(Reason::Exhausted)
+            .bury(slug, Some(config.tombstone_lifetime), &mut *conn)
+            .await?;
+        webhooks.deleted(slug, Reason::Exhausted, conn).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use rocket::http::{uri::Absolute, Status};
+
+    use super::*;
+    use crate::types::KeyPolicy;
+
+    fn redirect(status: RedirectStatus, accesses: Option<u16>, expiry: Option<i64>) -> Record {
+        let target = Absolute::parse_owned(String::from("https://example.com")).unwrap();
+        let expiry = expiry.map(|hours| Utc::now() + Duration::hours(hours));
+
+        Record::url(target, status, String::from("slug"), accesses, expiry)
+    }
+
+    #[test]
+    fn only_permanent_redirects_are_cached() {
+        let cache_control = |record: &Record| {
+            let status = match record.data() {
+                RecordData::Url { status, .. } => *status,
+                _ => unreachable!(),
+            };
+
+            RecordResponse::cache_control(record, status)
+        };
+
+        for status in [
+            RedirectStatus::Found,
+            RedirectStatus::SeeOther,
+            RedirectStatus::Temporary,
+        ] {
+            assert_eq!(cache_control(&redirect(status, None, None)), "no-store");
+        }
+
+        /* Counted accesses must reach the server, however permanent the redirect */
+        assert_eq!(
+            cache_control(&redirect(RedirectStatus::Permanent, Some(3), None)),
+            "no-store"
+        );
+        assert_eq!(
+            cache_control(&redirect(RedirectStatus::Moved, None, None)),
+            format!("public, max-age={}", PINNED_MAX_AGE)
+        );
+
+        let max_age: i64 = cache_control(&redirect(RedirectStatus::Permanent, None, Some(1)))
+            .strip_prefix("public, max-age=")
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(max_age > 3590 && max_age <= 3600);
+    }
+
+    #[rocket::async_test]
+    async fn redirects_use_the_requested_status() {
+        let config = Config {
+            anonymous: KeyPolicy::default(),
+            ..Config::default()
+        };
+        let (client, _) = crate::api::client(config).await;
+
+        for (status, expected) in [
+            (None, Status::SeeOther),
+            (Some("301"), Status::MovedPermanently),
+            (Some("307"), Status::TemporaryRedirect),
+            (Some("200"), Status::BadRequest),
+        ] {
+            let mut request = client
+                .post("/url")
+                .remote("203.0.113.7:4711".parse().unwrap())
+                .header(Header::new("Host", "shrekd.example"))
+                .body("https://93.184.215.14/target");
+            if let Some(status) = status {
+                request = request.header(Header::new("Redirect-Status", status));
+            }
+
+            let response = request.dispatch().await;
+            if expected == Status::BadRequest {
+                assert_eq!(response.status(), expected);
+                continue;
+            }
+
+            let link = response.into_string().await.unwrap();
+            let path = Absolute::parse_owned(link).unwrap().path().to_string();
+            let response = client.get(path).dispatch().await;
+            assert_eq!(response.status(), expected);
+            assert_eq!(
+                response.headers().get_one("Location"),
+                Some("https://93.184.215.14/target")
+            );
+        }
+    }
+}
@@ -1,15 +1,89 @@
-use rocket::{http::Header, routes, Responder};
+use rocket::{
+    http::{Header, Status},
+    response::{self, Responder},
+    routes, Request, Response,
+};
 
+use crate::types::RateLimit;
+
+mod admin;
 mod file;
 mod get;
 mod paste;
+mod qr;
+mod records;
+mod retention;
+mod stats;
+mod tus;
 mod url;
 
 pub fn routes() -> Vec<rocket::Route> {
     /*! Return the list of `/` ::api routes */
-    routes![file::create, paste::create, url::create, get::get]
+    routes![
+        file::create,
+        paste::create,
+        url::create,
+        get::get,
+        get::reveal,
+        stats::stats,
+        stats::chart,
+        qr::qr,
+        tus::discover,
+        tus::create,
+        tus::status,
+        tus::append,
+        retention::preview,
+        records::list,
+        records::delete,
+        records::link,
+        admin::search,
+        admin::inspect,
+        admin::purge,
+        admin::pin,
+        admin::takedown,
+        admin::blocklist,
+        admin::block,
+        admin::unblock
+    ]
 }
 
-#[derive(Responder)]
-#[response(status = 201)]
-struct CreatedResponse(String, Header<'static>);
+/** The response to the creation of a record, with it's link, `Expiry` header and the state of the rolling quotas */
+struct CreatedResponse(String, Header<'static>, Option<RateLimit>);
+
+impl<'r> Responder<'r, 'static> for CreatedResponse {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = Response::build_from(self.0.respond_to(request)?);
+
+        response.status(Status::Created).header(self.1);
+        for header in self.2.iter().flat_map(RateLimit::headers) {
+            response.header(header);
+        }
+
+        response.ok()
+    }
+}
+
+/** Build a client over the ::api routes, with the `config` and an empty in-memory Redis database */
+#[cfg(test)]
+pub(crate) async fn client(
+    config: crate::Config,
+) -> (rocket::local::asynchronous::Client, crate::types::Memory) {
+    let (pool, memory) = crate::types::Pool::memory();
+    let topology =
+        crate::types::Topology::Standalone(redis::Client::open(config.redis_url.as_str()).unwrap());
+
+    let rocket = rocket::build().mount("/", routes());
+    let grammar = crate::types::SlugGrammar::new(&config, rocket.routes());
+    let rocket = rocket
+        .manage(pool)
+        .manage(topology)
+        .manage(config)
+        .manage(grammar);
+
+    (
+        rocket::local::asynchronous::Client::untracked(rocket)
+            .await
+            .unwrap(),
+        memory,
+    )
+}
@@ -1,20 +1,27 @@
-use rocket::{http::Header, post, response::Responder, uri, State};
+use chrono::{DateTime, Utc};
+use rocket::{http::Header, post, response::Responder, State};
 
 use super::CreatedResponse;
 use crate::{
     config::Config,
-    types::{HostBase, Record, RecordSettings},
+    types::{Blocklist, HostBase, Identity, Pool, Record, RecordSettings, RecordType, SlugGrammar},
     Error, Result,
 };
 
 #[post("/paste", data = "<data>")]
 pub async fn create<'r>(
     data: Result<Vec<u8>, std::io::Error>,
-    host: HostBase<'_>,
+    identity: Result<Identity, Error<'static>>,
+    host: Result<HostBase<'_>, Error<'static>>,
     settings: RecordSettings,
+    grammar: &State<SlugGrammar>,
     config: &State<Config>,
-    redis: &State<redis::Client>,
+    redis: &State<Pool>,
 ) -> Result<impl Responder<'r, 'static>> {
+    let identity = identity?;
+    let host = host?;
+    identity.authorize(RecordType::Paste, &settings)?;
+
     /* If the paste data is malformed return an error */
     let data = data
         .map_err(|err| Error::PasteCreation(err.to_string()))
@@ -22,18 +29,54 @@ pub async fn create<'r>(
             String::from_utf8(bytes).map_err(|err| Error::PasteCreation(err.to_string()))
         })?;
 
-    let mut conn = redis.get_async_connection().await?;
+    let mut conn = redis.get();
+
+    /* Ensure the client's rolling quotas allow for this paste */
+    let throttle = identity.throttle();
+    let charge = throttle.check(data.len() as u64, &mut conn).await?;
+
+    let mut slug = None;
+    let persisted: Result<(Record, DateTime<Utc>)> = async {
+        /* Ensure the paste is not blocked */
+        Blocklist::check_paste(&data, &mut conn).await?;
+
+        /* Compute the slug and the appropriate storage path from it */
+        let slug = slug.insert(settings.slug(grammar, config, None, &mut conn).await?);
+
+        /* Compute the Record's max age from it's size */
+        let max_age = identity.max_age(config.paste_curve()?.compute_for(data.len() as u64));
+        let expiry = settings.expiry(Some(max_age)).unwrap(); // <- unwrap here is safe, because the Option conditioned by the `max_age` parameter
 
-    /* Compute the slug and the appropriate storage path from it */
-    let slug = settings.slug(config, &mut conn).await?;
+        /* Instanciate a new record from it */
+        let record = Record::paste(data, slug.clone(), settings.accesses(), Some(expiry))
+            .burn_after_reading(settings.burn())
+            .require_signature(settings.signed())
+            .owned_by(identity.name());
 
-    /* Instanciate a new record from it */
-    let record = Record::paste(data, slug, settings.accesses(), settings.expiry(None));
+        tracing::debug!("Received a new paste creation {:?}", record);
 
-    tracing::debug!("Received a new paste creation {:?}", record);
+        /* Finally try to push the record */
+        record.persist(&mut conn).await?;
 
-    /* Finally try to push the record */
-    record.persist(&mut conn).await?;
+        Ok((record, expiry))
+    }
+    .await;
+
+    /* Give back the slug whatever happened, since it's either held by the record by now or free again */
+    if let Some(slug) = &slug {
+        Record::unclaim(slug, &mut conn).await?;
+    }
+    /* Only the created records count in the rolling quotas */
+    if persisted.is_err() {
+        throttle.refund(&charge, &mut conn).await?;
+    }
+    let (record, expiry) = persisted?;
+
+    throttle.track(&record, &mut conn).await?;
+    config
+        .webhooks()
+        .created(&record, settings.notify(), &mut conn)
+        .await?;
 
     tracing::debug!(
         "Successfully persisted the paste with the slug `{}`",
@@ -41,8 +84,106 @@ pub async fn create<'r>(
     );
 
     Ok(CreatedResponse(
-        host.with(uri!(super::get::get(slug = record.slug())))
-            .to_string(),
-        Header::new("Expiry", "-1"),
+        super::get::link(&record, &host, config),
+        Header::new("Expiry", expiry.timestamp().to_string()),
+        charge.limit(),
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::{Header, Status};
+
+    use super::*;
+    use crate::types::KeyPolicy;
+
+    #[rocket::async_test]
+    async fn failed_pastes_give_back_their_slug() {
+        /* A misconfigured retention curve fails the creation once the slug is claimed */
+        let config = crate::Config {
+            anonymous: KeyPolicy::default(),
+            paste_retention_min_age: 2,
+            paste_retention_max_age: 1,
+            ..crate::Config::default()
+        };
+        let (client, _) = crate::api::client(config).await;
+
+        let response = client
+            .post("/paste")
+            .remote("203.0.113.7:4711".parse().unwrap())
+            .header(Header::new("Host", "shrekd.example"))
+            .header(Header::new("Custom-Slug", "vanity"))
+            .body("content")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::InternalServerError);
+
+        let mut conn = client.rocket().state::<Pool>().unwrap().get();
+        assert!(Record::claim("vanity", "someone", 60, &mut conn)
+            .await
+            .unwrap());
+    }
+
+    #[rocket::async_test]
+    async fn taken_slugs_are_not_held() {
+        let config = crate::Config {
+            anonymous: KeyPolicy::default(),
+            ..crate::Config::default()
+        };
+        let (client, _) = crate::api::client(config).await;
+
+        for expected in [Status::Created, Status::Conflict] {
+            let response = client
+                .post("/paste")
+                .remote("203.0.113.7:4711".parse().unwrap())
+                .header(Header::new("Host", "shrekd.example"))
+                .header(Header::new("Custom-Slug", "vanity"))
+                .header(Header::new("Slug-Strict", "true"))
+                .body("content")
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), expected);
+
+            /* Neither the created record nor the refused one hold the claim afterwards */
+            let mut conn = client.rocket().state::<Pool>().unwrap().get();
+            assert!(Record::claim("vanity", "someone", 60, &mut conn)
+                .await
+                .unwrap());
+            Record::unclaim("vanity", &mut conn).await.unwrap();
+        }
+    }
+
+    #[rocket::async_test]
+    async fn only_created_pastes_count_in_the_quotas() {
+        let config = crate::Config {
+            anonymous: KeyPolicy {
+                max_records_per_hour: Some(2),
+                ..KeyPolicy::default()
+            },
+            ..crate::Config::default()
+        };
+        let (client, _) = crate::api::client(config).await;
+        let paste = |slug: &'static str| {
+            client
+                .post("/paste")
+                .remote("203.0.113.7:4711".parse().unwrap())
+                .header(Header::new("Host", "shrekd.example"))
+                .header(Header::new("Custom-Slug", slug))
+                .header(Header::new("Slug-Strict", "true"))
+                .body("content")
+                .dispatch()
+        };
+
+        let response = paste("vanity").await;
+        assert_eq!(response.status(), Status::Created);
+        assert_eq!(response.headers().get_one("RateLimit-Limit"), Some("2"));
+        assert_eq!(response.headers().get_one("RateLimit-Remaining"), Some("1"));
+
+        /* The refused paste is given back to the quota */
+        assert_eq!(paste("vanity").await.status(), Status::Conflict);
+
+        let response = paste("other").await;
+        assert_eq!(response.status(), Status::Created);
+        assert_eq!(response.headers().get_one("RateLimit-Remaining"), Some("0"));
+    }
+}
@@ -0,0 +1,199 @@
+use qrcode::{render::svg, types::Color, EcLevel, QrCode};
+use rocket::{get, http::ContentType, uri, FromForm, FromFormField, Responder, State};
+
+use crate::{
+    config::Config,
+    types::{HostBase, Pool, Record},
+    Error, Result,
+};
+
+/** Default width and height of the QR codes, in pixels */
+const DEFAULT_SIZE: u32 = 256;
+/** Maximum width and height of the QR codes, in pixels */
+const MAX_SIZE: u32 = 2048;
+/** Width of the quiet zone around the PNG QR codes, in modules */
+const QUIET_ZONE: u32 = 4;
+
+#[derive(FromFormField, Debug, Clone, Copy)]
+pub enum Format {
+    Svg,
+    Png,
+}
+
+/** The error correction levels, recovering from about 7%, 15%, 25% and 30% of damage */
+#[derive(FromFormField, Debug, Clone, Copy)]
+pub enum Correction {
+    L,
+    M,
+    Q,
+    H,
+}
+
+impl From<Correction> for EcLevel {
+    fn from(correction: Correction) -> Self {
+        match correction {
+            Correction::L => EcLevel::L,
+            Correction::M => EcLevel::M,
+            Correction::Q => EcLevel::Q,
+            Correction::H => EcLevel::H,
+        }
+    }
+}
+
+#[derive(FromForm)]
+pub struct QrQuery {
+    /** Image format, `svg` or `png` */
+    format: Option<Format>,
+    /** Minimum width and height of the image, in pixels */
+    size: Option<u32>,
+    /** Error correction level, `l`, `m`, `q` or `h` */
+    ec: Option<Correction>,
+    /** Expiry of the signed link, for the records requiring a signature */
+    exp: Option<i64>,
+    /** Signature of the signed link, for the records requiring a signature */
+    sig: Option<String>,
+}
+
+#[derive(Debug, Responder)]
+pub enum QrResponse {
+    /* The image content types are unknown to the derive, so they are given as headers */
+    Svg(String, ContentType),
+    Png(Vec<u8>, ContentType),
+}
+
+/** Render the `code` as a grayscale PNG of at least `size` pixels wide */
+fn png(code: &QrCode, size: u32) -> Result<Vec<u8>> {
+    let width = code.width() as u32;
+    let modules = width + QUIET_ZONE * 2;
+    let scale = size.div_ceil(modules).max(1);
+    let pixels = modules * scale;
+    let colors = code.to_colors();
+
+    let mut image = vec![0xff; (pixels * pixels) as usize];
+    for (index, _) in colors
+        .iter()
+        .enumerate()
+        .filter(|(_, color)| **color == Color::Dark)
+    {
+        let (x, y) = (
+            index as u32 % width + QUIET_ZONE,
+            index as u32 / width + QUIET_ZONE,
+        );
+
+        for row in y * scale..(y + 1) * scale {
+            let start = (row * pixels + x * scale) as usize;
+            image[start..start + scale as usize].fill(0);
+        }
+    }
+
+    let mut data = Vec::new();
+    let mut encoder = png::Encoder::new(&mut data, pixels, pixels);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&image))
+        .map_err(|err| Error::Intrinsics(err.to_string().into()))?;
+
+    Ok(data)
+}
+
+#[get("/<slug>/qr?<query..>")]
+pub async fn qr(
+    slug: String,
+    query: QrQuery,
+    host: Result<HostBase<'_>, Error<'static>>,
+    config: &State<Config>,
+    redis: &State<Pool>,
+) -> Result<QrResponse> {
+    let host = host?;
+    let mut conn = redis.get();
+
+    /* The record is only looked up, rendering it's link is not an access */
+    let record = Record::find(&slug, &mut conn).await?;
+
+    /* The bare link of a signed record is refused, so it's code must carry a valid signature along */
+    super::get::verify(&record, query.exp, query.sig.as_deref(), config)?;
+    let signature = match record.is_signed() {
+        true => (query.exp, query.sig),
+        false => (None, None),
+    };
+
+    let url = host
+        .with(uri!(super::get::get(
+            slug = record.slug(),
+            exp = signature.0,
+            sig = signature.1
+        )))
+        .to_string();
+    let size = query.size.unwrap_or(DEFAULT_SIZE).min(MAX_SIZE);
+
+    let code = QrCode::with_error_correction_level(
+        url.as_bytes(),
+        query.ec.unwrap_or(Correction::M).into(),
+    )
+    .map_err(|err| Error::Intrinsics(err.to_string().into()))?;
+
+    Ok(match query.format.unwrap_or(Format::Svg) {
+        Format::Svg => QrResponse::Svg(
+            code.render::<svg::Color>()
+                .min_dimensions(size, size)
+                .build(),
+            ContentType::SVG,
+        ),
+        Format::Png => QrResponse::Png(png(&code, size)?, ContentType::PNG),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::{Header, Status};
+
+    use crate::types::{ApiKey, KeyPolicy};
+
+    #[rocket::async_test]
+    async fn signed_records_get_signed_codes() {
+        let config = crate::Config {
+            signing_secret: Some(String::from("secret")),
+            api_keys: vec![ApiKey {
+                name: String::from("owner"),
+                hash: ApiKey::hash("key"),
+                policy: KeyPolicy::default(),
+            }],
+            ..crate::Config::default()
+        };
+        let (client, _) = crate::api::client(config).await;
+
+        let response = client
+            .post("/paste")
+            .header(Header::new("Host", "shrekd.example"))
+            .header(Header::new("Api-Key", "key"))
+            .header(Header::new("Require-Signature", "true"))
+            .body("content")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+
+        /* The link returned upon creation is signed, so that the code of it's bare form is refused but not it's own */
+        let link = response.into_string().await.unwrap();
+        let link = link.trim_start_matches("http://shrekd.example");
+        let (path, query) = link.split_once('?').unwrap();
+
+        let response = client
+            .get(format!("{}/qr", path))
+            .header(Header::new("Host", "shrekd.example"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Forbidden);
+
+        let response = client
+            .get(format!("{}/qr?{}&size=128", path, query))
+            .header(Header::new("Host", "shrekd.example"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client.get(link.to_string()).dispatch().await;
+        assert_eq!(response.into_string().await.unwrap(), "content");
+    }
+}
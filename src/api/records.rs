@@ -0,0 +1,277 @@
+use chrono::Utc;
+use rocket::{delete, get, post, serde::json::Json, uri, State};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    types::{HostBase, Identity, Pool, PooledConnection, Reason, Record, RecordType, Tombstone},
+    Error, Result,
+};
+
+#[derive(Serialize)]
+pub struct RecordEntry {
+    slug: String,
+    /** Type of the record */
+    #[serde(rename = "type")]
+    kind: RecordType,
+    /** Size of the record's data, in bytes */
+    size: usize,
+    /** Expiry timestamp of the record, if applicable */
+    expiry: Option<i64>,
+    /** Remaining number of accesses, if applicable */
+    accesses: Option<u16>,
+}
+
+#[derive(Deserialize)]
+pub struct Deletion {
+    /** Slugs of the records to be deleted */
+    slugs: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct Deleted {
+    /** Slugs of the records that were actually deleted */
+    deleted: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct SignedLink {
+    /** The signed link to the record */
+    url: String,
+    /** Expiry timestamp of the link */
+    expiry: i64,
+}
+
+/** Ensure the [`Identity`] is backed by an API key, and get it's name */
+pub(super) fn owner(identity: &Identity) -> Result<&str> {
+    identity
+        .name()
+        .ok_or_else(|| Error::Unauthorized("an API key is required to manage it's records".into()))
+}
+
+/** Fetch the `owner`'s records, pruning the ones that are gone from it's index */
+async fn records(owner: &str, conn: &mut PooledConnection) -> Result<Vec<Record>> {
+    let mut records = Vec::new();
+
+    for slug in Record::owned(owner, &mut *conn).await? {
+        match Record::fetch(&slug, &mut *conn).await? {
+            Some(record) => records.push(record),
+            None => Record::disown(&slug, &mut *conn).await?,
+        }
+    }
+
+    Ok(records)
+}
+
+#[get("/api/records")]
+pub async fn list(
+    identity: Result<Identity, Error<'static>>,
+    redis: &State<Pool>,
+) -> Result<Json<Vec<RecordEntry>>> {
+    let identity = identity?;
+    let owner = owner(&identity)?;
+
+    let mut conn = redis.get();
+
+    let mut entries: Vec<_> = records(owner, &mut conn)
+        .await?
+        .into_iter()
+        .map(|record| RecordEntry {
+            slug: record.slug().to_string(),
+            kind: record.data().kind(),
+            size: record.data().size(),
+            expiry: record.expiry().map(|expiry| expiry.timestamp()),
+            accesses: record.accesses(),
+        })
+        .collect();
+
+    /* Soonest expiring records first, like the quota's eviction order */
+    entries.sort_by_key(|entry| (entry.expiry.unwrap_or(i64::MAX), entry.slug.clone()));
+
+    Ok(Json(entries))
+}
+
+#[delete("/api/records", data = "<deletion>")]
+pub async fn delete(
+    deletion: Json<Deletion>,
+    identity: Result<Identity, Error<'static>>,
+    config: &State<Config>,
+    redis: &State<Pool>,
+) -> Result<Json<Deleted>> {
+    let identity = identity?;
+    let owner = owner(&identity)?;
+
+    let mut conn = redis.get();
+    let quota = config.quota();
+    let webhooks = config.webhooks();
+
+    let mut deleted = Vec::new();
+    for slug in &deletion.slugs {
+        /* Only the records of the caller may be deleted, others are silently skipped */
+        let record = match Record::fetch(slug, &mut conn).await? {
+            Some(record) if record.owner() == Some(owner) => record,
+            _ => continue,
+        };
+
+        tracing::info!("Deleting {:?} on behalf of `{}`", record, owner);
+
+        record.delete(&mut conn).await?;
+        Tombstone::new(Reason::Deleted)
+            .bury(slug, Some(config.tombstone_lifetime), &mut conn)
+            .await?;
+        webhooks.deleted(slug, Reason::Deleted, &mut conn).await?;
+        quota.release(slug, &mut conn).await?;
+
+        deleted.push(slug.clone());
+    }
+
+    Ok(Json(Deleted { deleted }))
+}
+
+#[post("/api/records/<slug>/link?<lifetime>")]
+pub async fn link(
+    slug: String,
+    lifetime: Option<u64>,
+    identity: Result<Identity, Error<'static>>,
+    host: Result<HostBase<'_>, Error<'static>>,
+    config: &State<Config>,
+    redis: &State<Pool>,
+) -> Result<Json<SignedLink>> {
+    let identity = identity?;
+    let host = host?;
+    let owner = owner(&identity)?;
+
+    let signer = config
+        .signer()
+        .ok_or_else(|| Error::NotFound(String::from("signed links")))?;
+
+    let mut conn = redis.get();
+
+    /* Records of others are reported as missing, so their existence is not leaked */
+    let record = match Record::fetch(&slug, &mut conn).await? {
+        Some(record) if record.owner() == Some(owner) => record,
+        _ => return Err(Error::NotFound(slug)),
+    };
+
+    let lifetime = lifetime
+        .unwrap_or(config.signed_link_lifetime)
+        .min(config.signed_link_max_lifetime);
+    let expiry = Utc::now().timestamp() + lifetime as i64;
+    let sig = signer.sign(record.slug(), expiry);
+
+    tracing::debug!("Minting a link to {:?} until {}", record, expiry);
+
+    Ok(Json(SignedLink {
+        url: host
+            .with(uri!(super::get::get(
+                slug = record.slug(),
+                exp = Some(expiry),
+                sig = Some(sig)
+            )))
+            .to_string(),
+        expiry,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use redis::AsyncCommands;
+    use rocket::{
+        http::{Header, Status},
+        local::asynchronous::Client,
+        serde::json::{self, Value},
+    };
+
+    use super::*;
+    use crate::types::{ApiKey, KeyPolicy, STORAGE_PREFIX};
+
+    async fn client() -> Client {
+        let config = Config {
+            api_keys: ["alice", "bob"]
+                .iter()
+                .map(|name| ApiKey {
+                    name: String::from(*name),
+                    hash: ApiKey::hash(name),
+                    policy: KeyPolicy::default(),
+                })
+                .collect(),
+            ..Config::default()
+        };
+        let client = crate::api::client(config).await.0;
+
+        let mut conn = client.rocket().state::<Pool>().unwrap().get();
+        for (slug, owner, hours) in [
+            ("later", "alice", Some(2)),
+            ("never", "alice", None),
+            ("sooner", "alice", Some(1)),
+            ("stale", "alice", None),
+            ("other", "bob", None),
+        ] {
+            let expiry = hours.map(|hours| Utc::now() + Duration::hours(hours));
+            Record::paste(String::from("content"), slug.into(), None, expiry)
+                .owned_by(Some(owner))
+                .persist(&mut conn)
+                .await
+                .unwrap();
+        }
+
+        /* A record gone without being disowned, like one that expired */
+        conn.del::<_, ()>([STORAGE_PREFIX, "stale"].concat())
+            .await
+            .unwrap();
+
+        client
+    }
+
+    #[rocket::async_test]
+    async fn owners_list_their_own_records() {
+        let client = client().await;
+
+        let response = client
+            .get("/api/records")
+            .header(Header::new("Api-Key", "alice"))
+            .dispatch()
+            .await;
+        let entries: Value = json::from_str(&response.into_string().await.unwrap()).unwrap();
+        let slugs: Vec<_> = entries
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["slug"].as_str().unwrap())
+            .collect();
+        assert_eq!(slugs, ["sooner", "later", "never"]);
+
+        let mut conn = client.rocket().state::<Pool>().unwrap().get();
+        assert!(!Record::owned("alice", &mut conn)
+            .await
+            .unwrap()
+            .contains(&String::from("stale")));
+
+        let response = client
+            .get("/api/records")
+            .remote("203.0.113.7:4711".parse().unwrap())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+
+    #[rocket::async_test]
+    async fn owners_only_delete_their_own_records() {
+        let client = client().await;
+
+        let response = client
+            .delete("/api/records")
+            .header(Header::new("Api-Key", "alice"))
+            .body(r#"{ "slugs": ["sooner", "other", "missing"] }"#)
+            .dispatch()
+            .await;
+        let deleted: Value = json::from_str(&response.into_string().await.unwrap()).unwrap();
+        assert_eq!(deleted["deleted"], json::json!(["sooner"]));
+
+        let mut conn = client.rocket().state::<Pool>().unwrap().get();
+        assert!(!Record::exists("sooner", &mut conn).await.unwrap());
+        assert!(Tombstone::exists("sooner", &mut conn).await.unwrap());
+        assert!(Record::exists("other", &mut conn).await.unwrap());
+    }
+}
@@ -0,0 +1,142 @@
+use chrono::{Duration, Utc};
+use rocket::{data::ToByteUnit, get, serde::json::Json, FromForm, State};
+use serde::Serialize;
+
+use crate::{
+    config::Config,
+    types::{Identity, RecordType},
+    Error, Result,
+};
+
+#[derive(FromForm)]
+pub struct PreviewQuery {
+    /** Size of the record's data, in bytes */
+    size: u64,
+    /** Type of the record */
+    #[field(name = "type")]
+    kind: RecordType,
+}
+
+#[derive(Serialize)]
+pub struct Preview {
+    /** Type of the record */
+    #[serde(rename = "type")]
+    kind: RecordType,
+    /** Size of the record's data, in bytes */
+    size: u64,
+    /** Retention of the record if created now, in seconds */
+    retention: u64,
+    /** Expiry timestamp of the record if created now */
+    expiry: i64,
+    /** Maximum retention the record may be granted, in seconds */
+    max_retention: u64,
+    /** Maximum expiry timestamp the record may be granted if created now */
+    max_expiry: i64,
+}
+
+#[get("/api/retention?<query..>")]
+pub fn preview(
+    query: PreviewQuery,
+    identity: Result<Identity, Error<'static>>,
+    config: &State<Config>,
+) -> Result<Json<Preview>> {
+    let identity = identity?;
+    let PreviewQuery { size, kind } = query;
+
+    /* Compute the retention the same way the creation routes do, with the caller's key policy, sized records can't
+     * outlive their curve */
+    let (max_size, retention, max_retention) = match kind {
+        RecordType::File => {
+            let retention = identity.max_age(config.curve()?.compute_for(size));
+
+            (
+                identity.max_file_size(config.max_file_size),
+                retention,
+                retention,
+            )
+        }
+        RecordType::Paste => {
+            let retention = identity.max_age(config.paste_curve()?.compute_for(size));
+
+            (config.max_paste_size, retention, retention)
+        }
+        RecordType::Url => {
+            let max_retention = identity.max_age(config.url_max_age);
+
+            (
+                config.max_url_size,
+                u64::min(config.url_default_age, max_retention),
+                max_retention,
+            )
+        }
+    };
+
+    if size > max_size {
+        return Err(Error::PayloadTooLarge(format!(
+            "the size of {} exceeds the maximum size of {}",
+            size.bytes(),
+            max_size.bytes()
+        )));
+    }
+
+    let now = Utc::now();
+
+    Ok(Json(Preview {
+        kind,
+        size,
+        retention,
+        expiry: (now + Duration::seconds(retention as i64)).timestamp(),
+        max_retention,
+        max_expiry: (now + Duration::seconds(max_retention as i64)).timestamp(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::Header;
+    use rocket::serde::json::{self, Value};
+
+    use crate::types::{ApiKey, KeyPolicy};
+
+    #[rocket::async_test]
+    async fn previews_follow_the_key_policy() {
+        let config = crate::Config {
+            api_keys: vec![ApiKey {
+                name: String::from("short"),
+                hash: ApiKey::hash("key"),
+                policy: KeyPolicy {
+                    max_age: Some(60),
+                    max_file_size: Some(1000),
+                    ..KeyPolicy::default()
+                },
+            }],
+            ..crate::Config::default()
+        };
+        let (client, _) = crate::api::client(config).await;
+
+        let preview = |query: String, key: Option<&'static str>| {
+            let mut request = client
+                .get(format!("/api/retention?{}", query))
+                .remote("203.0.113.7:4711".parse().unwrap());
+            if let Some(key) = key {
+                request = request.header(Header::new("Api-Key", key));
+            }
+
+            request.dispatch()
+        };
+
+        let response = preview(String::from("size=100&type=url"), None).await;
+        let anonymous: Value = json::from_str(&response.into_string().await.unwrap()).unwrap();
+        assert_eq!(anonymous["max_retention"], 60 * 60 * 24 * 365);
+
+        for kind in ["file", "paste", "url"] {
+            let response = preview(format!("size=100&type={}", kind), Some("key")).await;
+            let preview: Value = json::from_str(&response.into_string().await.unwrap()).unwrap();
+            assert_eq!(preview["retention"], 60);
+            assert_eq!(preview["max_retention"], 60);
+        }
+
+        let response = preview(String::from("size=1001&type=file"), Some("key")).await;
+        assert_eq!(response.status().code, 413);
+    }
+}
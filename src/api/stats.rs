@@ -0,0 +1,41 @@
+use rocket::{get, response::content, serde::json::Json, State};
+
+use super::records::owner;
+use crate::{
+    types::{Analytics, Identity, Pool, Record, RecordData},
+    Error, Result,
+};
+
+#[get("/<slug>/stats", format = "json", rank = 1)]
+pub async fn stats(
+    slug: String,
+    identity: Result<Identity, Error<'static>>,
+    redis: &State<Pool>,
+) -> Result<Json<Analytics>> {
+    let identity = identity?;
+    let owner = owner(&identity)?;
+
+    let mut conn = redis.get();
+
+    /* Records of others are reported as missing, so their existence is not leaked */
+    match Record::fetch(&slug, &mut conn).await? {
+        Some(record)
+            if record.owner() == Some(owner) && matches!(record.data(), RecordData::Url { .. }) => {
+        }
+        _ => return Err(Error::NotFound(slug)),
+    }
+
+    Ok(Json(Analytics::fetch(&slug, &mut conn).await?))
+}
+
+/** Render the chart page, which fetches the analytics itself with the owner's API key */
+#[get("/<slug>/stats", rank = 2)]
+pub async fn chart(slug: String) -> Result<content::Html<String>> {
+    let template = liquid::ParserBuilder::with_stdlib()
+        .build()?
+        .parse(include_str!("../../ui/stats.html"))?;
+
+    let globals = liquid::object!({ "slug": slug });
+
+    Ok(content::Html(template.render(&globals)?))
+}
@@ -0,0 +1,530 @@
+/*!
+ * Implementation of the [tus](https://tus.io/protocols/resumable-upload.html) resumable upload protocol,
+ * `1.0.0` core with the `creation` and `expiration` extensions
+ */
+use rocket::{
+    data::{Data, ToByteUnit},
+    head,
+    http::{Header, HeaderMap, Status},
+    options, patch, post,
+    request::{self, FromRequest, Request},
+    response::{self, Responder, Response},
+    uri, State,
+};
+use std::io::SeekFrom;
+use tokio::{
+    fs,
+    io::{AsyncSeekExt, AsyncWriteExt},
+};
+
+use crate::{
+    config::Config,
+    types::{
+        Blocklist, HostBase, Identity, Pool, PooledConnection, RateLimit, Record, RecordSettings,
+        RecordType, SlugGrammar, Upload,
+    },
+    Error, Result,
+};
+
+/** The supported version of the tus protocol */
+const TUS_VERSION: &str = "1.0.0";
+/** The supported extensions of the tus protocol */
+const TUS_EXTENSIONS: &str = "creation,expiration";
+/** The mandatory content type of `PATCH` requests */
+const TUS_CONTENT_TYPE: &str = "application/offset+octet-stream";
+
+/** Wrap the headers of a tus request, ensuring the client speaks a supported version */
+pub struct TusRequest<'r>(&'r HeaderMap<'r>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for TusRequest<'r> {
+    type Error = Error<'static>;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match req.headers().get_one("Tus-Resumable") {
+            Some(TUS_VERSION) => request::Outcome::Success(Self(req.headers())),
+            version => request::Outcome::Failure((
+                Status::PreconditionFailed,
+                Error::UnsupportedVersion(format!(
+                    "the `{}` tus protocol version is not supported",
+                    version.unwrap_or("unspecified")
+                )),
+            )),
+        }
+    }
+}
+
+impl<'r> TusRequest<'r> {
+    /** Parse the numeric value of the `name` header */
+    fn number(&self, name: &str) -> Result<u64> {
+        self.0
+            .get_one(name)
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| {
+                Error::FileUpload(format!("the `{}` header is missing or malformed", name))
+            })
+    }
+
+    /** Extract the file name from the `Upload-Metadata` header, if any */
+    fn filename(&self) -> Option<String> {
+        self.0
+            .get_one("Upload-Metadata")?
+            .split(',')
+            .filter_map(|pair| {
+                let mut pair = pair.trim().splitn(2, ' ');
+
+                Some((pair.next()?, pair.next()?))
+            })
+            .find(|(key, _)| *key == "filename" || *key == "name")
+            .and_then(|(_, value)| base64::decode(value).ok())
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+    }
+}
+
+/** A tus protocol response, made of a status and headers */
+pub struct TusResponse(Status, Vec<Header<'static>>);
+
+impl<'r> Responder<'r, 'static> for TusResponse {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = Response::build();
+
+        response
+            .status(self.0)
+            .header(Header::new("Tus-Resumable", TUS_VERSION));
+        for header in self.1 {
+            response.header(header);
+        }
+
+        response.ok()
+    }
+}
+
+/** Format the `Upload-Expires` header from the [`Upload`]'s expiry */
+fn expires(upload: &Upload) -> Header<'static> {
+    Header::new(
+        "Upload-Expires",
+        upload
+            .expiry()
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string(),
+    )
+}
+
+#[options("/upload")]
+pub fn discover(config: &State<Config>) -> TusResponse {
+    TusResponse(
+        Status::NoContent,
+        vec![
+            Header::new("Tus-Version", TUS_VERSION),
+            Header::new("Tus-Extension", TUS_EXTENSIONS),
+            Header::new("Tus-Max-Size", config.max_file_size.to_string()),
+        ],
+    )
+}
+
+#[post("/upload")]
+pub async fn create(
+    tus: Result<TusRequest<'_>, Error<'static>>,
+    identity: Result<Identity, Error<'static>>,
+    host: Result<HostBase<'_>, Error<'static>>,
+    settings: RecordSettings,
+    grammar: &State<SlugGrammar>,
+    config: &State<Config>,
+    redis: &State<Pool>,
+) -> Result<TusResponse> {
+    let tus = tus?;
+    let identity = identity?;
+    let host = host?;
+    identity.authorize(RecordType::File, &settings)?;
+
+    let length = tus.number("Upload-Length")?;
+    let max_file_size = identity.max_file_size(config.max_file_size);
+    if length > max_file_size {
+        return Err(Error::PayloadTooLarge(format!(
+            "the upload of {} exceeds the maximum file size of {}",
+            length.bytes(),
+            max_file_size.bytes()
+        )));
+    }
+
+    let mut conn = redis.get();
+
+    /* Refuse the custom slug now, rather than after the whole upload */
+    if let Some(slug) = settings.custom_slug().filter(|slug| !slug.is_empty()) {
+        grammar.validate(slug)?;
+    }
+
+    let upload = Upload::new(
+        tus.filename().unwrap_or_else(|| String::from("upload")),
+        length,
+        config.upload_expiry,
+        settings,
+        identity,
+    );
+
+    tracing::debug!("Received a resumable upload creation {:?}", upload);
+
+    /* Ensure the client's rolling quotas allow for this upload, which counts as soon as it's created */
+    let throttle = upload.identity().throttle();
+    let charge = throttle.check(length, &mut conn).await?;
+
+    /* Hold the custom slug for the whole upload, so that it's still free upon completion */
+    let mut claimed = None;
+    if let Some(slug) = upload
+        .settings()
+        .custom_slug()
+        .filter(|slug| !slug.is_empty())
+    {
+        if Record::claim(slug, upload.id(), config.upload_expiry, &mut conn).await? {
+            if !Record::is_taken(slug, &mut conn).await? {
+                claimed = Some(slug);
+            } else {
+                /* The claim is of no use on a taken slug, and must not hold it any longer */
+                Record::unclaim(slug, &mut conn).await?;
+            }
+        }
+        if claimed.is_none() && upload.settings().strict(config) {
+            throttle.refund(&charge, &mut conn).await?;
+
+            return Err(Error::SlugTaken(slug.to_string()));
+        }
+    }
+
+    /* Reserve the whole upload in the storage quota now, so that partial uploads are accounted for */
+    let quota = config.quota();
+    if let Err(err) = quota.reserve(length, &mut conn).await {
+        if let Some(slug) = claimed {
+            Record::unclaim(slug, &mut conn).await?;
+        }
+        throttle.refund(&charge, &mut conn).await?;
+
+        return Err(err);
+    }
+
+    /* Push the upload, mark it's reservation, and create the empty partial file */
+    let pushed: Result<()> = async {
+        upload.persist(&mut conn).await?;
+        quota
+            .mark(&Upload::key(upload.id()), length, &mut conn)
+            .await?;
+        fs::File::create(Upload::path(config.temp(), upload.id())).await?;
+
+        Ok(())
+    }
+    .await;
+    if let Err(err) = pushed {
+        upload.delete(&mut conn).await?;
+        if !quota.release_upload(upload.id(), &mut conn).await? {
+            quota.unreserve(length, &mut conn).await?;
+        }
+        if let Some(slug) = claimed {
+            Record::unclaim(slug, &mut conn).await?;
+        }
+        throttle.refund(&charge, &mut conn).await?;
+
+        return Err(err);
+    }
+
+    let mut headers = vec![
+        Header::new(
+            "Location",
+            host.with(uri!(status(id = upload.id()))).to_string(),
+        ),
+        expires(&upload),
+    ];
+    headers.extend(charge.limit().iter().flat_map(RateLimit::headers));
+
+    Ok(TusResponse(Status::Created, headers))
+}
+
+#[head("/upload/<id>")]
+pub async fn status(
+    id: String,
+    tus: Result<TusRequest<'_>, Error<'static>>,
+    redis: &State<Pool>,
+) -> Result<TusResponse> {
+    tus?;
+
+    let mut conn = redis.get();
+
+    let upload = Upload::fetch(&id, &mut conn)
+        .await?
+        .ok_or(Error::NotFound(id))?;
+
+    Ok(TusResponse(
+        Status::Ok,
+        vec![
+            Header::new("Upload-Offset", upload.offset().to_string()),
+            Header::new("Upload-Length", upload.length().to_string()),
+            Header::new("Cache-Control", "no-store"),
+            expires(&upload),
+        ],
+    ))
+}
+
+#[patch("/upload/<id>", data = "<data>")]
+pub async fn append(
+    id: String,
+    data: Data<'_>,
+    tus: Result<TusRequest<'_>, Error<'static>>,
+    host: Result<HostBase<'_>, Error<'static>>,
+    grammar: &State<SlugGrammar>,
+    config: &State<Config>,
+    redis: &State<Pool>,
+) -> Result<TusResponse> {
+    let tus = tus?;
+    let host = host?;
+
+    if tus.0.get_one("Content-Type") != Some(TUS_CONTENT_TYPE) {
+        return Err(Error::UnsupportedMediaType(format!(
+            "the content type must be `{}`",
+            TUS_CONTENT_TYPE
+        )));
+    }
+
+    let mut conn = redis.get();
+
+    /* Serialize the appends to the same upload, which would otherwise interleave their bytes */
+    let token = Upload::lock(&id, &mut conn)
+        .await?
+        .ok_or_else(|| Error::UploadLocked(id.clone()))?;
+
+    let appended = receive(&id, &token, data, &tus, host, grammar, config, &mut conn).await;
+    Upload::unlock(&id, &token, &mut conn).await?;
+
+    Ok(TusResponse(Status::NoContent, appended?))
+}
+
+/** Write the received bytes to the partial file of the [`Upload`] identified by `id`, whose lock is held with `token`,
+ * completing it when it's full */
+#[allow(clippy::too_many_arguments)] /* <- Every guard of the handler is passed along */
+async fn receive(
+    id: &str,
+    token: &str,
+    data: Data<'_>,
+    tus: &TusRequest<'_>,
+    host: HostBase<'_>,
+    grammar: &SlugGrammar,
+    config: &Config,
+    conn: &mut PooledConnection,
+) -> Result<Vec<Header<'static>>> {
+    let mut upload = Upload::fetch(id, &mut *conn)
+        .await?
+        .ok_or_else(|| Error::NotFound(id.to_string()))?;
+
+    let offset = tus.number("Upload-Offset")?;
+    if offset != upload.offset() {
+        return Err(Error::UploadOffset(upload.offset(), offset));
+    }
+
+    /* Write at the acknowledged offset, dropping whatever a previous interrupted append left past it */
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .open(Upload::path(config.temp(), upload.id()))
+        .await?;
+    file.set_len(upload.offset()).await?;
+    file.seek(SeekFrom::Start(upload.offset())).await?;
+
+    /* Receive the bytes up to the announced length, and account for those on disk even if the client went away */
+    let remaining = upload.length() - upload.offset();
+    let received = tokio::select! {
+        received = data.open(remaining.bytes()).stream_to(&mut file) => received,
+        /* However slow the client, the lock must outlive the append, which is abandonned otherwise */
+        err = Upload::hold(id, token, conn.clone()) => return Err(err),
+    };
+    file.flush().await?;
+    let written = (file.metadata().await?.len())
+        .saturating_sub(upload.offset())
+        .min(remaining);
+
+    /* Only the holder of the lock may move the offset, which no one else can have moved meanwhile then */
+    if !Upload::renew(id, token, &mut *conn).await? {
+        return Err(Error::UploadLocked(id.to_string()));
+    }
+    upload.advance(written);
+    upload.persist(&mut *conn).await?;
+
+    tracing::debug!(
+        "Received {} for the upload `{}`, now at {}/{}",
+        written.bytes(),
+        upload.id(),
+        upload.offset(),
+        upload.length()
+    );
+
+    received?;
+
+    let mut headers = vec![
+        Header::new("Upload-Offset", upload.offset().to_string()),
+        expires(&upload),
+    ];
+
+    if upload.is_complete() {
+        headers.extend(complete(upload, host, grammar, config, conn).await?);
+    }
+
+    Ok(headers)
+}
+
+/** Turn a complete [`Upload`] into a `file` [`Record`], and drop the [`Upload`] */
+async fn complete(
+    upload: Upload,
+    host: HostBase<'_>,
+    grammar: &SlugGrammar,
+    config: &Config,
+    conn: &mut PooledConnection,
+) -> Result<Vec<Header<'static>>> {
+    let settings = upload.settings();
+
+    /* Compute the slug and the appropriate storage path from it */
+    let slug = settings
+        .slug(grammar, config, Some(upload.id()), &mut *conn)
+        .await?;
+    let storage = fs::canonicalize(&config.data_dir).await?.join(&slug);
+    let size = upload.length();
+
+    /* Compute the Record's max age from it's size */
+    let max_age = upload.identity().max_age(config.curve()?.compute_for(size));
+    let expiry = settings.expiry(Some(max_age)).unwrap(); // <- unwrap here is safe, because the Option conditioned by the `max_age` parameter
+
+    /* Instanciate a new record from it */
+    let record = Record::file(
+        upload.name().to_string(),
+        storage.clone(),
+        size as usize,
+        slug,
+        settings.accesses(),
+        Some(expiry),
+    )
+    .burn_after_reading(settings.burn())
+    .require_signature(settings.signed())
+    .owned_by(upload.identity().name());
+
+    /* Finally move the partial file in place, along with the reservation made upon creation */
+    fs::rename(Upload::path(config.temp(), upload.id()), &storage).await?;
+    let quota = config.quota();
+    quota
+        .transfer(upload.id(), record.slug(), &mut *conn)
+        .await?;
+
+    /* Ensure it's content is not blocked, push the record and drop the upload */
+    let stored: Result<()> = async {
+        Blocklist::check_file(&storage, &mut *conn).await?;
+        record.persist(&mut *conn).await
+    }
+    .await;
+    if let Err(err) = stored {
+        /* Remove the file, give back the reservation and the slug */
+        quota.release(record.slug(), &mut *conn).await?;
+        Record::unclaim(record.slug(), &mut *conn).await?;
+        upload.delete(&mut *conn).await?;
+
+        return Err(err);
+    }
+    /* The record holds the slug by now, while the claim was kept up to here in case the upload is completed again */
+    Record::unclaim(record.slug(), &mut *conn).await?;
+    upload
+        .identity()
+        .throttle()
+        .track(&record, &mut *conn)
+        .await?;
+    config
+        .webhooks()
+        .created(&record, settings.notify(), &mut *conn)
+        .await?;
+    upload.delete(&mut *conn).await?;
+
+    tracing::debug!(
+        "Successfully completed the upload `{}` with the slug `{}`",
+        upload.id(),
+        record.slug()
+    );
+
+    Ok(vec![
+        Header::new("Record-Location", super::get::link(&record, &host, config)),
+        Header::new("Expiry", expiry.timestamp().to_string()),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::local::asynchronous::{Client, LocalResponse};
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::types::KeyPolicy;
+
+    /** Build a client storing it's files in a fresh directory */
+    async fn client() -> (Client, PathBuf) {
+        let data_dir = std::env::temp_dir().join(format!("shrekd-{}", rand::random::<u64>()));
+        let config = Config {
+            anonymous: KeyPolicy::default(),
+            data_dir: data_dir.clone(),
+            ..Config::default()
+        };
+        fs::create_dir_all(config.temp()).await.unwrap();
+
+        (crate::api::client(config).await.0, data_dir)
+    }
+
+    async fn patch<'c>(
+        client: &'c Client,
+        location: &str,
+        offset: u64,
+        body: &'static str,
+    ) -> LocalResponse<'c> {
+        client
+            .patch(location.to_string())
+            .header(Header::new("Host", "shrekd.example"))
+            .header(Header::new("Tus-Resumable", TUS_VERSION))
+            .header(Header::new("Content-Type", TUS_CONTENT_TYPE))
+            .header(Header::new("Upload-Offset", offset.to_string()))
+            .body(body)
+            .dispatch()
+            .await
+    }
+
+    #[rocket::async_test]
+    async fn uploads_are_appended_at_their_offset() {
+        let (client, data_dir) = client().await;
+
+        let response = client
+            .post("/upload")
+            .remote("203.0.113.7:4711".parse().unwrap())
+            .header(Header::new("Host", "shrekd.example"))
+            .header(Header::new("Tus-Resumable", TUS_VERSION))
+            .header(Header::new("Upload-Length", "10"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+        let location = response.headers().get_one("Location").unwrap();
+        let location = location.trim_start_matches("http://shrekd.example");
+        let id = location.trim_start_matches("/upload/");
+
+        /* Appends must start where the upload is at */
+        let response = patch(&client, location, 5, "56789").await;
+        assert_eq!(response.status(), Status::Conflict);
+
+        let response = patch(&client, location, 0, "0123").await;
+        assert_eq!(response.status(), Status::NoContent);
+        assert_eq!(response.headers().get_one("Upload-Offset"), Some("4"));
+
+        /* Concurrent appends are refused while the lock is held */
+        let mut conn = client.rocket().state::<Pool>().unwrap().get();
+        let token = Upload::lock(id, &mut conn).await.unwrap().unwrap();
+        let response = patch(&client, location, 4, "456789").await;
+        assert_eq!(response.status(), Status::Locked);
+        Upload::unlock(id, &token, &mut conn).await.unwrap();
+
+        let response = patch(&client, location, 4, "456789").await;
+        assert_eq!(response.status(), Status::NoContent);
+        assert_eq!(response.headers().get_one("Upload-Offset"), Some("10"));
+
+        let record = response.headers().get_one("Record-Location").unwrap();
+        let record = record.trim_start_matches("http://shrekd.example");
+        let response = client.get(record.to_string()).dispatch().await;
+        assert_eq!(response.into_string().await.unwrap(), "0123456789");
+
+        fs::remove_dir_all(data_dir).await.unwrap();
+    }
+}
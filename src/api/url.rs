@@ -1,20 +1,27 @@
-use rocket::{http::Header, post, response::Responder, uri, State};
+use chrono::{DateTime, Utc};
+use rocket::{http::Header, post, response::Responder, State};
 
 use super::CreatedResponse;
 use crate::{
     config::Config,
-    types::{HostBase, Record, RecordSettings},
+    types::{Blocklist, HostBase, Identity, Pool, Record, RecordSettings, RecordType, SlugGrammar},
     Error, Result,
 };
 
 #[post("/url", data = "<data>")]
 pub async fn create<'r>(
     data: Result<String, std::io::Error>,
-    host: HostBase<'_>,
+    identity: Result<Identity, Error<'static>>,
+    host: Result<HostBase<'_>, Error<'static>>,
     settings: RecordSettings,
+    grammar: &State<SlugGrammar>,
     config: &State<Config>,
-    redis: &State<redis::Client>,
+    redis: &State<Pool>,
 ) -> Result<impl Responder<'r, 'static>> {
+    let identity = identity?;
+    let host = host?;
+    identity.authorize(RecordType::Url, &settings)?;
+
     /* If the url data is malformed return an error */
     let url = data.map_err(|err| Error::UrlCreation(err.to_string()))?;
     let url = rocket::http::uri::Absolute::parse_owned(url)
@@ -38,18 +45,67 @@ pub async fn create<'r>(
         ));
     }
 
-    let mut conn = redis.get_async_connection().await?;
+    /* Ensure the target complies with the configured policy, and is not a loop back to us */
+    config.target_policy().check(&url, host.host()).await?;
+
+    let mut conn = redis.get();
+
+    /* Ensure the client's rolling quotas allow for this redirect */
+    let throttle = identity.throttle();
+    let charge = throttle
+        .check(url.to_string().len() as u64, &mut conn)
+        .await?;
+
+    let mut slug = None;
+    let persisted: Result<(Record, DateTime<Utc>)> = async {
+        /* Ensure the target's domain is not blocked, the authority was checked above */
+        if let Some(authority) = url.authority() {
+            Blocklist::check_domain(authority.host(), &mut conn).await?;
+        }
+
+        /* Compute the slug and the appropriate storage path from it */
+        let slug = slug.insert(settings.slug(grammar, config, None, &mut conn).await?);
+
+        /* Compute the Record's expiry from the configured lifetimes */
+        let expiry =
+            settings.expiry_or(config.url_default_age, identity.max_age(config.url_max_age));
 
-    /* Compute the slug and the appropriate storage path from it */
-    let slug = settings.slug(config, &mut conn).await?;
+        /* Instanciate a new record from it */
+        let record = Record::url(
+            url,
+            settings.redirect_status(),
+            slug.clone(),
+            settings.accesses(),
+            Some(expiry),
+        )
+        .burn_after_reading(settings.burn())
+        .require_signature(settings.signed())
+        .owned_by(identity.name());
 
-    /* Instanciate a new record from it */
-    let record = Record::url(url, slug, settings.accesses(), settings.expiry(None));
+        tracing::debug!("Received a new url creation {:?}", record);
 
-    tracing::debug!("Received a new url creation {:?}", record);
+        /* Finally try to push the record */
+        record.persist(&mut conn).await?;
+
+        Ok((record, expiry))
+    }
+    .await;
+
+    /* Give back the slug whatever happened, since it's either held by the record by now or free again */
+    if let Some(slug) = &slug {
+        Record::unclaim(slug, &mut conn).await?;
+    }
+    /* Only the created records count in the rolling quotas */
+    if persisted.is_err() {
+        throttle.refund(&charge, &mut conn).await?;
+    }
+    let (record, expiry) = persisted?;
 
-    /* Finally try to push the record */
-    record.persist(&mut conn).await?;
+    throttle.track(&record, &mut conn).await?;
+    config
+        .webhooks()
+        .created(&record, settings.notify(), &mut conn)
+        .await?;
 
     tracing::debug!(
         "Successfully persisted the redirect with the slug `{}`",
@@ -57,8 +113,8 @@ pub async fn create<'r>(
     );
 
     Ok(CreatedResponse(
-        host.with(uri!(super::get::get(slug = record.slug())))
-            .to_string(),
-        Header::new("Expiry", "-1"),
+        super::get::link(&record, &host, config),
+        Header::new("Expiry", expiry.timestamp().to_string()),
+        charge.limit(),
     ))
 }
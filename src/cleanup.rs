@@ -0,0 +1,299 @@
+/*!
+ * Cleanup of the files left behind by expired or deleted records, driven by the Redis keyspace events
+ * and backed by a polling sweeper for when these are unavailable
+ */
+use futures::StreamExt;
+use redis::AsyncCommands;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::fs;
+
+use crate::{
+    types::{self, PooledConnection, Quota, Reason, Record, Tombstone, Topology, Upload, Webhooks},
+    Config, Result,
+};
+
+/** Minimum delay before reconnecting to the keyspace events, in seconds */
+const BACKOFF_MIN: u64 = 1;
+/** Maximum delay before reconnecting to the keyspace events, in seconds */
+const BACKOFF_MAX: u64 = 60;
+
+/** Run the cleanup tasks forever, surviving any disconnection from the Redis server */
+pub async fn run(config: Config, topology: Topology, conn: PooledConnection) {
+    let listening = Arc::new(AtomicBool::new(false));
+
+    /* The sweeper takes over whenever the keyspace events are not being received */
+    tokio::spawn(sweeper(config.clone(), conn.clone(), listening.clone()));
+
+    let mut backoff = BACKOFF_MIN;
+    loop {
+        match listen(&config, &topology, conn.clone(), &listening).await {
+            Ok(()) => tracing::warn!("The keyspace events subscription ended unexpectedly"),
+            Err(err) => tracing::warn!("The keyspace events subscription failed: {}", err),
+        }
+
+        if listening.swap(false, Ordering::SeqCst) {
+            /* We were up and running, so this is a fresh disconnection */
+            backoff = BACKOFF_MIN;
+        }
+
+        tracing::info!("Reconnecting to the keyspace events in {} seconds", backoff);
+        tokio::time::sleep(Duration::from_secs(backoff)).await;
+
+        backoff = u64::min(backoff * 2, BACKOFF_MAX);
+    }
+}
+
+/** Listen for `del` and `expired` Redis keyspace events to cleanup expired files */
+async fn listen(
+    config: &Config,
+    topology: &Topology,
+    mut conn: PooledConnection,
+    listening: &AtomicBool,
+) -> Result<()> {
+    let mut streams = Vec::new();
+    let mut enabled = true;
+
+    /* Keyspace events are local to each node, so we subscribe to every master */
+    for node in topology.masters(&mut conn).await? {
+        let mut node_conn = node.get_async_connection().await?;
+        let db = node.get_connection_info().redis.db;
+
+        /* Enable keyspace events in the redis server, which may be forbidden by managed offerings */
+        if let Err(err) = redis::cmd("CONFIG")
+            .arg("SET")
+            .arg("notify-keyspace-events")
+            .arg("Egx") /* `Egx` means E: keyevent events, with types g: general and x: expired */
+            .query_async::<_, ()>(&mut node_conn)
+            .await
+        {
+            tracing::warn!(
+                "Could not enable the keyspace events on {:?}, relying on the sweeper: {}",
+                node.get_connection_info().addr,
+                err
+            );
+
+            enabled = false;
+        }
+
+        /* Subscribe to the relevant events, in the configured database */
+        let mut pubsub = node_conn.into_pubsub();
+        pubsub
+            .psubscribe(format!("__keyevent@{}__:expired", db))
+            .await?;
+        pubsub
+            .psubscribe(format!("__keyevent@{}__:del", db))
+            .await?;
+
+        tracing::debug!(
+            "Subscribed to the keyspace events of {:?} on the database `{}`",
+            node.get_connection_info().addr,
+            db
+        );
+
+        /* Mark the end of each stream, since the others keep going when a single node goes away */
+        let messages = pubsub.into_on_message().map(Some);
+        streams.push(
+            messages
+                .chain(futures::stream::once(async { None }))
+                .boxed(),
+        );
+    }
+
+    let mut events = futures::stream::select_all(streams);
+
+    /* Catch up with whatever happened while we were not listening */
+    sweep(config, &mut conn).await?;
+    listening.store(enabled, Ordering::SeqCst);
+
+    /* Any node going away is a reconnection, which also follows a failover or a resharding */
+    while let Some(Some(msg)) = events.next().await {
+        tracing::trace!("Received a new notification: {:#?}", msg);
+
+        let key: String = match msg.get_payload() {
+            Ok(key) => key,
+            Err(err) => {
+                tracing::warn!("Received a malformed notification: {}", err);
+                continue;
+            }
+        };
+
+        let expired = msg.get_channel_name().ends_with(":expired");
+
+        if let Err(err) = collect(config, &key, expired, &mut conn).await {
+            tracing::error!("Failed to cleanup after the key `{}`: {}", key, err);
+        }
+    }
+
+    Ok(())
+}
+
+/** Remove the files associated with the `key` if it is gone for good, and mark it if it has `expired` */
+async fn collect(
+    config: &Config,
+    key: &str,
+    expired: bool,
+    conn: &mut PooledConnection,
+) -> Result<()> {
+    /* Remove the partial file of abandonned or completed resumable uploads, and give back their reservation */
+    if let Some(id) = key.strip_prefix(types::UPLOAD_PREFIX) {
+        match fs::remove_file(Upload::path(config.temp(), id)).await {
+            Ok(()) => tracing::debug!("Removed the partial file of the upload `{}`", id),
+            _ => tracing::debug!("Partial file was not found, so we have nothing to remove"),
+        }
+
+        return config.quota().release_upload(id, conn).await.map(drop);
+    }
+
+    /* Retrieve the slug from the key, and check that the key hasn't been re-created */
+    match key.strip_prefix(types::STORAGE_PREFIX) {
+        Some(slug) if !Record::exists(slug, &mut *conn).await? => {
+            if expired {
+                Tombstone::new(Reason::Expired)
+                    .bury(slug, Some(config.tombstone_lifetime), &mut *conn)
+                    .await?;
+                config.webhooks().expired(slug, &mut *conn).await?;
+            }
+
+            Record::disown(slug, &mut *conn).await?;
+
+            /* Removing the file if needed and found, and give back it's size to the quota */
+            config.quota().release(slug, conn).await
+        }
+        _ => Ok(()),
+    }
+}
+
+/** Periodically sweep the leftover files while the keyspace events are not being received */
+async fn sweeper(config: Config, mut conn: PooledConnection, listening: Arc<AtomicBool>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.sweep_interval.max(1)));
+
+    loop {
+        interval.tick().await;
+
+        if listening.load(Ordering::SeqCst) {
+            continue;
+        }
+
+        if let Err(err) = sweep(&config, &mut conn).await {
+            tracing::error!("Failed to sweep the leftover files: {}", err);
+        }
+    }
+}
+
+/** Remove the files of the `file` records and resumable uploads that are gone */
+async fn sweep(config: &Config, conn: &mut PooledConnection) -> Result<()> {
+    tracing::debug!("Sweeping the files of the gone records and uploads");
+
+    /* Walk the index of `file` records, since expired or exhausted records leave no trace */
+    let records: Vec<(String, i64)> = conn
+        .zscan::<_, (String, i64)>(types::EXPIRIES_KEY)
+        .await?
+        .collect()
+        .await;
+
+    let now = chrono::Utc::now().timestamp();
+    for (slug, expiry) in records {
+        let key = [types::STORAGE_PREFIX, &slug].concat();
+
+        collect(config, &key, expiry <= now, conn).await?;
+    }
+
+    /* Walk the records subscribed to webhooks, whose expiry may not have been notified */
+    for (slug, expiry) in Webhooks::subscriptions(&mut *conn).await? {
+        if expiry.is_some_and(|expiry| expiry <= now) {
+            let key = [types::STORAGE_PREFIX, &slug].concat();
+
+            collect(config, &key, true, conn).await?;
+        }
+    }
+
+    /* Walk the partial files of the resumable uploads */
+    let mut entries = fs::read_dir(config.temp()).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name();
+        let id = match name
+            .to_str()
+            .and_then(|name| name.strip_prefix(types::UPLOAD_PREFIX))
+        {
+            Some(id) => id,
+            None => continue,
+        };
+
+        if Upload::fetch(id, &mut *conn).await?.is_none() {
+            collect(config, &[types::UPLOAD_PREFIX, id].concat(), false, conn).await?;
+        }
+    }
+
+    /* Walk the reservations of the resumable uploads, whose partial file may already be gone */
+    for id in Quota::uploads(&mut *conn).await? {
+        if Upload::fetch(&id, &mut *conn).await?.is_none() {
+            collect(config, &[types::UPLOAD_PREFIX, &id].concat(), false, conn).await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::*;
+    use crate::types::Pool;
+
+    #[rocket::async_test]
+    async fn sweeps_collect_the_gone_records_and_uploads() {
+        let (pool, _) = Pool::memory();
+        let mut conn = pool.get();
+        let data_dir = std::env::temp_dir().join(format!("shrekd-{}", rand::random::<u64>()));
+        let config = Config {
+            data_dir: data_dir.clone(),
+            ..Config::default()
+        };
+        fs::create_dir_all(config.temp()).await.unwrap();
+        let quota = config.quota();
+
+        for (slug, expiry) in [
+            ("gone", Utc::now() - Duration::minutes(1)),
+            ("kept", Utc::now() + Duration::hours(1)),
+        ] {
+            let path = data_dir.join(slug);
+            fs::write(&path, [0; 10]).await.unwrap();
+            quota.reserve(10, &mut conn).await.unwrap();
+            quota.mark(slug, 10, &mut conn).await.unwrap();
+
+            Record::file(slug.into(), path, 10, slug.into(), None, Some(expiry))
+                .persist(&mut conn)
+                .await
+                .unwrap();
+        }
+
+        /* An upload abandonned while the keyspace events were not received */
+        fs::write(Upload::path(config.temp(), "abandonned"), [0; 5])
+            .await
+            .unwrap();
+        quota.reserve(5, &mut conn).await.unwrap();
+        quota
+            .mark(&Upload::key("abandonned"), 5, &mut conn)
+            .await
+            .unwrap();
+
+        sweep(&config, &mut conn).await.unwrap();
+
+        assert!(fs::metadata(data_dir.join("gone")).await.is_err());
+        assert!(Tombstone::exists("gone", &mut conn).await.unwrap());
+        assert!(fs::metadata(data_dir.join("kept")).await.is_ok());
+        assert!(fs::metadata(Upload::path(config.temp(), "abandonned"))
+            .await
+            .is_err());
+        assert_eq!(Quota::usage(&mut conn).await.unwrap(), 10);
+
+        fs::remove_dir_all(data_dir).await.unwrap();
+    }
+}
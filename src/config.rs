@@ -1,10 +1,17 @@
 use figment::{Figment, Metadata, Profile, Provider};
-use rocket::data::ToByteUnit;
+use rocket::{data::ToByteUnit, http::uri::Absolute};
 use serde::{Deserialize, Serialize};
 
+use ipnet::IpNet;
 use std::path::PathBuf;
 
-use crate::{types::RetentionCurve, Error, Result};
+use crate::{
+    types::{
+        ApiKey, KeyPolicy, Quota, QuotaPolicy, RedisMode, RetentionCurve, Shape, Signer, SlugStyle,
+        TargetPolicy, Webhooks,
+    },
+    Error, Result,
+};
 
 const TEMPDIR_NAME: &str = ".temporary";
 
@@ -16,12 +23,54 @@ pub struct Config {
     /** Port on which the server will be exposed */
     pub port: u16,
 
-    /** Redis server URL */
+    /** Public base url of the links returned to the clients, detected from the request's headers if unset */
+    pub public_base_url: Option<String>,
+    /** Networks of the reverse proxies trusted to set the `Forwarded` and `X-Forwarded-*` headers */
+    pub trusted_proxies: Vec<IpNet>,
+
+    /** Redis server URL, also providing the credentials and database for the other modes */
     pub redis_url: String,
+    /** Redis deployment mode, either `standalone`, `sentinel` or `cluster` */
+    pub redis_mode: RedisMode,
+    /** Redis Sentinels or Redis Cluster seed nodes URLs, defaults to the `redis_url` */
+    pub redis_nodes: Vec<String>,
+    /** Name of the master monitored by the Redis Sentinels */
+    pub redis_master: String,
+    /** Number of multiplexed connections to the Redis server */
+    pub redis_pool_size: usize,
+    /** Timeout of the Redis server's connections and commands, in milliseconds */
+    pub redis_timeout: u64,
     /** App's permanent data storage directory */
     pub data_dir: PathBuf,
     /** Random URI's slug length, in characters */
     pub slug_length: u8,
+    /** Style of the random slugs, `alphanumeric`, `unambiguous` or `words` */
+    pub slug_style: SlugStyle,
+    /** Characters of the `unambiguous` random slugs */
+    pub slug_alphabet: String,
+    /** Minimum entropy of the random slugs, in bits, from which their minimum length is computed */
+    pub slug_entropy: u32,
+    /** Characters allowed in the custom slugs */
+    pub slug_charset: String,
+    /** Minimum length of the custom slugs, in characters */
+    pub slug_min_length: u8,
+    /** Maximum length of the custom slugs, in characters */
+    pub slug_max_length: u8,
+    /** Whether a taken custom slug is refused by default, rather than replaced by a random one */
+    pub slug_strict: bool,
+    /** Words that can't be used as slugs, in addition to the first segments of the routes */
+    pub reserved_slugs: Vec<String>,
+
+    /** Token protecting the `/admin` routes, which are disabled if unset */
+    pub admin_token: Option<String>,
+
+    /** API keys allowed to create records, in addition to the ones stored in Redis */
+    pub api_keys: Vec<ApiKey>,
+    /** Whether records may be created without an API key */
+    pub allow_anonymous: bool,
+    /** Limits of the records created without an API key, conservative by default, the unset ones are
+     * kept when only some are overridden */
+    pub anonymous: KeyPolicy,
 
     /** Max file size, in bytes */
     pub max_file_size: u64,
@@ -30,10 +79,64 @@ pub struct Config {
     /** Max url size, in bytes */
     pub max_url_size: u64,
 
+    /** Total storage quota for files, in bytes, unlimited if unset */
+    pub storage_quota: Option<u64>,
+    /** Behavior when the storage quota is exceeded, either `reject` or `evict` */
+    pub quota_policy: QuotaPolicy,
+
+    /** Interval between the sweeps of leftover files, when keyspace events are unavailable, in seconds */
+    pub sweep_interval: u64,
+
+    /** Lifetime of the tombstones of dead records, in seconds, 0 disables them */
+    pub tombstone_lifetime: u64,
+
+    /** Lifetime of the tombstones of taken down records, in seconds, 0 disables them and they are kept forever if unset */
+    pub takedown_lifetime: Option<u64>,
+
+    /** Lifetime of unfinished resumable uploads, in seconds */
+    pub upload_expiry: u64,
+
     /** Retention min age, in seconds */
     pub retention_min_age: u64,
     /** Retention max age, in seconds */
     pub retention_max_age: u64,
+    /** Retention curve shape, `linear`, `quadratic`, `cubic`, `exponential` or a step table */
+    pub retention_shape: Shape,
+
+    /** Paste retention min age, in seconds */
+    pub paste_retention_min_age: u64,
+    /** Paste retention max age, in seconds */
+    pub paste_retention_max_age: u64,
+    /** Paste retention curve shape, see `retention_shape` */
+    pub paste_retention_shape: Shape,
+
+    /** Url retention age when none is requested, in seconds */
+    pub url_default_age: u64,
+    /** Url retention max age, in seconds */
+    pub url_max_age: u64,
+    /** Domains the url redirects are restricted to, including their subdomains, any if empty */
+    pub url_allowed_domains: Vec<String>,
+    /** Domains the url redirects may not point to, including their subdomains */
+    pub url_denied_domains: Vec<String>,
+    /** Whether the url redirects and the records' webhooks may point to loopback, private or link-local addresses */
+    pub url_allow_private: bool,
+
+    /** Webhook notified of every record's lifecycle events, if any */
+    pub webhook_url: Option<String>,
+    /** Secret used to sign the webhooks' payloads in the `Shrekd-Signature` header, unsigned if unset */
+    pub webhook_secret: Option<String>,
+    /** Number of retries of a failed webhook delivery */
+    pub webhook_retries: u32,
+
+    /** Secret used to sign the links of the records requiring a signature, which are disabled if unset */
+    pub signing_secret: Option<String>,
+    /** Lifetime of the signed links when none is requested, in seconds */
+    pub signed_link_lifetime: u64,
+    /** Max lifetime of the signed links, in seconds */
+    pub signed_link_max_lifetime: u64,
+
+    /** Case-insensitive `User-Agent` fragments of the link preview bots, which never consume a record */
+    pub preview_bots: Vec<String>,
 }
 
 impl Default for Config {
@@ -41,14 +144,79 @@ impl Default for Config {
         Config {
             address: String::from("0.0.0.0"),
             port: 8000,
+            public_base_url: None,
+            trusted_proxies: vec![
+                IpNet::from(std::net::IpAddr::from([127, 0, 0, 1])),
+                IpNet::from(std::net::IpAddr::from([0, 0, 0, 0, 0, 0, 0, 1])),
+            ],
             redis_url: String::from("redis://127.0.0.1:6379"),
+            redis_mode: RedisMode::Standalone,
+            redis_nodes: Vec::new(),
+            redis_master: String::from("mymaster"),
+            redis_pool_size: 4,
+            redis_timeout: 5000,
             data_dir: PathBuf::from("/tmp/.shrekd"),
             slug_length: 13,
+            slug_style: SlugStyle::Alphanumeric,
+            slug_alphabet: String::from("23456789abcdefghijkmnpqrstuvwxyzABCDEFGHJKLMNPQRSTUVWXYZ"),
+            slug_entropy: 64,
+            slug_charset: String::from(
+                "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_",
+            ),
+            slug_min_length: 3,
+            slug_max_length: 64,
+            slug_strict: false,
+            reserved_slugs: Vec::new(),
+            admin_token: None,
+            api_keys: Vec::new(),
+            allow_anonymous: true,
+            anonymous: KeyPolicy::anonymous(),
             max_file_size: 128.megabytes().into(),
             max_paste_size: 1.megabytes().into(),
             max_url_size: 32.kilobytes().into(),
+            storage_quota: None,
+            quota_policy: QuotaPolicy::Reject,
+            sweep_interval: 60,                   /* 1 minute */
+            tombstone_lifetime: 60 * 60 * 24 * 7, /* 1 week */
+            takedown_lifetime: None,
+            upload_expiry: 60 * 60 * 24,             /* 1 day */
             retention_min_age: 60 * 60 * 24 * 7,     /* 1 week */
             retention_max_age: 60 * 60 * 24 * 7 * 3, /* 3 weeks */
+            retention_shape: Shape::Quadratic,
+            paste_retention_min_age: 60 * 60 * 24 * 7, /* 1 week */
+            paste_retention_max_age: 60 * 60 * 24 * 7 * 4, /* 4 weeks */
+            paste_retention_shape: Shape::Quadratic,
+            url_default_age: 60 * 60 * 24 * 30, /* 30 days */
+            url_max_age: 60 * 60 * 24 * 365,    /* 1 year */
+            url_allowed_domains: Vec::new(),
+            url_denied_domains: Vec::new(),
+            url_allow_private: false,
+            webhook_url: None,
+            webhook_secret: None,
+            webhook_retries: 5,
+            signing_secret: None,
+            signed_link_lifetime: 60 * 60,               /* 1 hour */
+            signed_link_max_lifetime: 60 * 60 * 24 * 30, /* 30 days */
+            preview_bots: [
+                "Slackbot",
+                "Slack-ImgProxy",
+                "SkypeUriPreview",
+                "MicrosoftPreview",
+                "Teams",
+                "Discordbot",
+                "TelegramBot",
+                "WhatsApp",
+                "Twitterbot",
+                "facebookexternalhit",
+                "LinkedInBot",
+                "Mattermost",
+                "Iframely",
+                "redditbot",
+                "Embedly",
+            ]
+            .iter()
+            .map(|bot| bot.to_string())
+            .collect(),
         }
     }
 }
@@ -57,7 +225,12 @@ impl Config {
     /* Allow the configuration to be extracted from any [`Provider`] */
     #[inline]
     pub fn from<T: Provider>(provider: T) -> Result<Self> {
-        Ok(Figment::from(provider).extract()?)
+        let config: Self = Figment::from(provider).extract()?;
+
+        /* Fail right away on the values whose type can't tell they are invalid */
+        config.base_url()?;
+
+        Ok(config)
     }
 
     /** Extract figment configuration from the environment */
@@ -66,6 +239,29 @@ impl Config {
         Figment::from(Config::default()).merge(figment::providers::Env::prefixed("SHREKD_"))
     }
 
+    /** Parse the public base url, which must be an absolute `http` or `https` url, if any */
+    pub fn base_url(&self) -> Result<Option<Absolute<'_>>> {
+        self.public_base_url
+            .as_deref()
+            .map(|base| match Absolute::parse(base.trim_end_matches('/')) {
+                Ok(url)
+                    if (url.scheme() == "http" || url.scheme() == "https")
+                        && url.authority().is_some()
+                        && url.query().is_none() =>
+                {
+                    Ok(url.into_normalized())
+                }
+                _ => Err(Error::Config(
+                    format!(
+                        "the public base url `{}` must be an absolute `http` or `https` url",
+                        base
+                    )
+                    .into(),
+                )),
+            })
+            .transpose()
+    }
+
     /** Compute and get the temporary file path */
     #[inline]
     pub fn temp(&self) -> PathBuf {
@@ -79,9 +275,61 @@ impl Config {
             self.retention_min_age,
             self.retention_max_age,
             self.max_file_size,
+            self.retention_shape.clone(),
+        )
+        .map_err(|err| Error::Intrinsics(err.to_string().into()))
+    }
+
+    /** Get the paste's [`RetentionCurve`] from the current configuration */
+    #[inline]
+    pub fn paste_curve(&self) -> Result<RetentionCurve> {
+        RetentionCurve::new(
+            self.paste_retention_min_age,
+            self.paste_retention_max_age,
+            self.max_paste_size,
+            self.paste_retention_shape.clone(),
         )
         .map_err(|err| Error::Intrinsics(err.to_string().into()))
     }
+
+    /** Get the storage [`Quota`] from the current configuration */
+    #[inline]
+    pub fn quota(&self) -> Quota {
+        Quota::new(
+            self.data_dir.clone(),
+            self.storage_quota,
+            self.quota_policy,
+            self.tombstone_lifetime,
+            self.webhooks(),
+        )
+    }
+
+    /** Get the [`Webhooks`] from the current configuration */
+    #[inline]
+    pub fn webhooks(&self) -> Webhooks {
+        Webhooks::new(
+            self.webhook_url.clone(),
+            self.webhook_secret.clone(),
+            self.webhook_retries,
+            self.url_allow_private,
+        )
+    }
+
+    /** Get the url redirects' [`TargetPolicy`] from the current configuration */
+    #[inline]
+    pub fn target_policy(&self) -> TargetPolicy<'_> {
+        TargetPolicy::new(
+            &self.url_allowed_domains,
+            &self.url_denied_domains,
+            self.url_allow_private,
+        )
+    }
+
+    /** Get the link [`Signer`] from the current configuration, if signed links are enabled */
+    #[inline]
+    pub fn signer(&self) -> Option<Signer> {
+        self.signing_secret.clone().map(Signer::new)
+    }
 }
 
 use figment::value::{Dict, Map};
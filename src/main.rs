@@ -3,19 +3,25 @@
  * SHaRe, SHoRten oK Daemon, simple file, paste & url sharing daemon
  */
 #![allow(clippy::large_enum_variant)] /* <- This allows for storing the `rocket::response::Redirect` type inside enums, because these are HUGE */
+#![allow(clippy::result_large_err)] /* <- Same goes for the `Error` type, which embeds foreign error types */
 
 use figment::Figment;
+use std::time::Duration;
 use tokio::fs;
 
 mod api;
 mod ui;
 
+mod cleanup;
 mod config;
 mod types;
+mod webhooks;
 
 pub use config::Config;
 pub use types::{Error, Result};
 
+use types::{Pool, SlugGrammar, Topology};
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     /* Load application configuration from the default provider */
@@ -38,14 +44,26 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .await
         .expect("Failed to create the temporary data directory");
 
-    tracing::info!("Initializing the Redis client with {}", config.redis_url);
+    tracing::info!(
+        "Initializing the Redis client in {:?} mode with {}",
+        config.redis_mode,
+        config.redis_url
+    );
 
-    /* Instanciate the Redis client */
-    let redis = redis::Client::open(config.redis_url.as_str())
-        .expect("Failed to initialize the Redis client");
+    /* Resolve the Redis deployment's topology, and the connection pool from it */
+    let topology = Topology::resolve(&config)
+        .await
+        .expect("Failed to resolve the Redis deployment");
+    let pool = Pool::connect(
+        &topology,
+        config.redis_pool_size,
+        Duration::from_millis(config.redis_timeout),
+    )
+    .await;
 
     /* Get the rocket instance from the configuration */
-    let rocket = rocket(config.clone(), redis.clone())
+    let conn = pool.get();
+    let rocket = rocket(config.clone(), topology.clone(), pool)
         .ignite()
         .await
         .expect("Failed to ignite the `Rocket` instance");
@@ -56,73 +74,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         config.port
     );
 
-    /* Macro launches concurently two expressions and resumes when one finishes */
-    tokio::select! {
-        /* This launches the cleanup handler */
-        res = cleanup(config.clone(), redis) => {
-            Ok(res?)
-        },
-        /* This launches the server */
-        res = rocket.launch() => {
-            Ok(res?)
-        }
-    }
-}
+    /* This launches the cleanup handler in the background, it never returns */
+    tokio::spawn(cleanup::run(config.clone(), topology, conn.clone()));
+
+    /* This launches the webhooks delivery in the background, it never returns either */
+    tokio::spawn(webhooks::run(config.clone(), conn));
 
-async fn cleanup(config: Config, redis: redis::Client) -> crate::Result<()> {
-    /*! Listen for `del` and `expired` Redis keyspace events to cleanup expired files */
-    use futures::StreamExt;
-    use std::path::Path;
-
-    let mut conn = redis.get_async_connection().await?;
-
-    /* Enable keyspace events in the redis server */
-    redis::cmd("CONFIG")
-        .arg("SET")
-        .arg("notify-keyspace-events")
-        .arg("Egx") /* `Egx` means E: keyevent events, with types g: general and x: expired */
-        .query_async(&mut conn)
-        .await?;
-
-    /* Subscribe to the relevant events */
-    let mut pubsub = redis.get_async_connection().await?.into_pubsub();
-    pubsub.psubscribe("__keyevent@0__:expired").await?;
-    pubsub.psubscribe("__keyevent@0__:del").await?;
-
-    let mut events = pubsub.into_on_message();
-
-    loop {
-        use types::Record;
-
-        let msg = match events.next().await {
-            None => continue,
-            Some(msg) => msg,
-        };
-        tracing::trace!("Received a new notification: {:#?}", msg);
-
-        /* Retrieve the key, and split it into prefix and slug */
-        let mut key: String = msg.get_payload()?;
-        let (slug, prefix) = (key.split_off(types::STORAGE_PREFIX.len()), key);
-
-        /* Check if the prefix is good and that the key hasn't been re-created */
-        if prefix != types::STORAGE_PREFIX || Record::exists(&slug, &mut conn).await? {
-            continue;
-        }
-
-        /* Removing the file if needed and found */
-        match fs::canonicalize(Path::new(&config.data_dir).join(&slug)).await {
-            /* File exists, we remove it */
-            Ok(path) => {
-                tracing::debug!("Removing the {:?} since it's record expired", path);
-                fs::remove_file(path).await?
-            }
-            /* Otherwise we skip the notification */
-            _ => tracing::debug!("File was not found, so we have nothing to remove"),
-        };
-    }
+    /* This launches the server */
+    Ok(rocket.launch().await?)
 }
 
-fn rocket(config: Config, redis: redis::Client) -> rocket::Rocket<rocket::Build> {
+fn rocket(config: Config, topology: Topology, pool: Pool) -> rocket::Rocket<rocket::Build> {
     /*! Configure the [`Rocket`] from the [`Config`] structure, and attach everything */
     let rocket = rocket::custom(
         Figment::from(rocket::Config::default())
@@ -134,12 +96,19 @@ fn rocket(config: Config, redis: redis::Client) -> rocket::Rocket<rocket::Build>
             .merge(("limits.string", &config.max_url_size)),
     )
     /* Mount `/` ::api routes */
-    .mount("/", api::routes())
-    /* Attach a redis client to the rocket instance */
-    .manage(redis)
-    /* Attach the config to the rocket instance */
-    .manage(config);
-
-    /* Attach the UI frontpage to Rocket and return it */
-    ui::attach(rocket)
+    .mount("/", api::routes());
+
+    /* Attach the UI frontpage to Rocket */
+    let rocket = ui::attach(rocket);
+
+    /* The custom slugs can't collide with the routes, so their first segments are reserved */
+    let grammar = SlugGrammar::new(&config, rocket.routes());
+
+    rocket
+        /* Attach the redis connection pool and topology to the rocket instance */
+        .manage(pool)
+        .manage(topology)
+        /* Attach the config and slug grammar to the rocket instance */
+        .manage(config)
+        .manage(grammar)
 }
@@ -0,0 +1,47 @@
+use rocket::{
+    outcome::Outcome,
+    request::{FromRequest, Request},
+};
+
+/** Get the `User-Agent` header from the [`Request`], and recognize the link preview bots */
+pub struct UserAgent {
+    preview: bool,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for UserAgent {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> rocket::request::Outcome<Self, Self::Error> {
+        let agent = request
+            .headers()
+            .get_one("User-Agent")
+            .unwrap_or_default()
+            .to_lowercase();
+
+        let preview = request
+            .rocket()
+            .state::<crate::Config>()
+            .map(|config| {
+                config
+                    .preview_bots
+                    .iter()
+                    .any(|bot| agent.contains(&bot.to_lowercase()))
+            })
+            .unwrap_or(false);
+
+        if preview {
+            tracing::debug!("Recognized a link preview bot in `{}`", agent);
+        }
+
+        Outcome::Success(Self { preview })
+    }
+}
+
+impl UserAgent {
+    /** Whether the request comes from a link preview bot, which must never consume a record */
+    #[inline]
+    pub const fn is_preview(&self) -> bool {
+        self.preview
+    }
+}
@@ -0,0 +1,254 @@
+use chrono::Utc;
+use redis::AsyncCommands;
+use rocket::{
+    http::uri::Absolute,
+    outcome::Outcome,
+    request::{FromRequest, Request},
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeMap, net::IpAddr};
+
+use super::{ClientIp, PooledConnection, Record, Result, ANALYTICS_PREFIX, VISITORS_PREFIX};
+
+/** Maximum length of the user agents kept in the analytics, in characters */
+const AGENT_LENGTH: usize = 128;
+
+/** Maximum length of the referrer domains kept in the analytics, being the longest domain name possible */
+const REFERRER_LENGTH: usize = 253;
+
+/** Maximum number of distinct referrers, and of distinct user agents, counted for a url redirect, the others being
+ * counted together */
+const DISTINCT_LIMIT: u64 = 50;
+
+/** The label under which the referrers, or user agents, over the [`DISTINCT_LIMIT`] are counted together */
+const OTHERS: &str = "(others)";
+
+/** Count a click on the analytics hash `KEYS[1]` on the day `ARGV[2]`, from the referrer `ARGV[3]` and the user agent
+ * `ARGV[4]` if not empty, counting at most `ARGV[1]` distinct values of each and the others together as `ARGV[5]` */
+const CLICK_SCRIPT: &str = r"
+local function count(kind, value)
+    if value == '' then
+        return
+    end
+
+    local field = kind .. ':' .. value
+    if redis.call('HEXISTS', KEYS[1], field) == 0 then
+        local distinct = 'distinct:' .. kind
+        if tonumber(redis.call('HGET', KEYS[1], distinct) or '0') >= tonumber(ARGV[1]) then
+            field = kind .. ':' .. ARGV[5]
+        else
+            redis.call('HINCRBY', KEYS[1], distinct, 1)
+        end
+    end
+    redis.call('HINCRBY', KEYS[1], field, 1)
+end
+
+redis.call('HINCRBY', KEYS[1], 'clicks', 1)
+redis.call('HINCRBY', KEYS[1], 'day:' .. ARGV[2], 1)
+count('referrer', ARGV[3])
+count('agent', ARGV[4])
+";
+
+/** The visit of a url redirect, as it's accounted in the analytics */
+pub struct Visit {
+    /** The client's address, only kept hashed to count the unique visitors */
+    address: Option<IpAddr>,
+    /** The domain of the `Referer` header, if any */
+    referrer: Option<String>,
+    agent: Option<String>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Visit {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> rocket::request::Outcome<Self, Self::Error> {
+        let address = request
+            .guard::<ClientIp>()
+            .await
+            .succeeded()
+            .map(ClientIp::into_inner);
+
+        /* Only the domain is kept, the full referrer may hold anything */
+        let referrer = request
+            .headers()
+            .get_one("Referer")
+            .and_then(|referrer| Absolute::parse(referrer).ok())
+            .and_then(|referrer| {
+                referrer.authority().map(|authority| {
+                    normalize(authority.host().trim_end_matches('.'), REFERRER_LENGTH)
+                        .to_lowercase()
+                })
+            })
+            .filter(|referrer| !referrer.is_empty());
+
+        let agent = request
+            .headers()
+            .get_one("User-Agent")
+            .map(|agent| normalize(agent, AGENT_LENGTH))
+            .filter(|agent| !agent.is_empty());
+
+        Outcome::Success(Self {
+            address,
+            referrer,
+            agent,
+        })
+    }
+}
+
+/** Trim the `value` and strip it's control characters, keeping at most `length` characters */
+fn normalize(value: &str, length: usize) -> String {
+    value
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(length)
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/** The click analytics of a url redirect, as shown to it's owner */
+#[derive(Serialize, Debug, Default)]
+pub struct Analytics {
+    /** Total number of clicks */
+    clicks: u64,
+    /** Estimated number of unique visitors */
+    visitors: u64,
+    /** Number of clicks by referrer domain */
+    referrers: BTreeMap<String, u64>,
+    /** Number of clicks by user agent */
+    agents: BTreeMap<String, u64>,
+    /** Number of clicks by day, as `YYYY-MM-DD` */
+    daily: BTreeMap<String, u64>,
+}
+
+impl Analytics {
+    #[inline]
+    fn key(slug: &str) -> String {
+        [ANALYTICS_PREFIX, slug].concat()
+    }
+
+    #[inline]
+    fn visitors_key(slug: &str) -> String {
+        [VISITORS_PREFIX, slug].concat()
+    }
+
+    /** Account for the `visit` of the `record`, expiring along with it */
+    pub async fn click(record: &Record, visit: &Visit, conn: &mut PooledConnection) -> Result<()> {
+        let key = Self::key(record.slug());
+        let visitors_key = Self::visitors_key(record.slug());
+
+        redis::Script::new(CLICK_SCRIPT)
+            .key(&key)
+            .arg(DISTINCT_LIMIT)
+            .arg(Utc::now().format("%Y-%m-%d").to_string())
+            .arg(visit.referrer.as_deref().unwrap_or_default())
+            .arg(visit.agent.as_deref().unwrap_or_default())
+            .arg(OTHERS)
+            .invoke_async::<_, ()>(conn)
+            .await?;
+
+        /* The visitors are told apart by a hash of their address, salted with the slug */
+        if let Some(address) = visit.address {
+            let visitor = hex::encode(Sha256::digest(
+                format!("{}:{}", record.slug(), address).as_bytes(),
+            ));
+            conn.pfadd::<_, _, ()>(&visitors_key, visitor).await?;
+        }
+
+        Self::expire(record, conn).await
+    }
+
+    /** Align the expiry of the `record`'s analytics with it's own */
+    pub async fn expire(record: &Record, conn: &mut PooledConnection) -> Result<()> {
+        for key in [Self::key(record.slug()), Self::visitors_key(record.slug())] {
+            match record.expiry() {
+                Some(expiry) => {
+                    conn.expire_at::<_, ()>(&key, expiry.timestamp() as usize)
+                        .await?
+                }
+                None => conn.persist::<_, ()>(&key).await?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /** Fetch the analytics of the record at `slug` */
+    pub async fn fetch(slug: &str, conn: &mut PooledConnection) -> Result<Self> {
+        let fields: BTreeMap<String, u64> = conn.hgetall(Self::key(slug)).await?;
+        let mut analytics = Analytics {
+            visitors: conn.pfcount(Self::visitors_key(slug)).await?,
+            ..Default::default()
+        };
+
+        for (field, count) in fields {
+            let (kind, value) = field.split_once(':').unwrap_or((&field, ""));
+
+            match kind {
+                "clicks" => analytics.clicks = count,
+                "day" => drop(analytics.daily.insert(value.to_string(), count)),
+                "referrer" => drop(analytics.referrers.insert(value.to_string(), count)),
+                "agent" => drop(analytics.agents.insert(value.to_string(), count)),
+                _ => (),
+            }
+        }
+
+        Ok(analytics)
+    }
+
+    /** Delete the analytics of the record at `slug`, once it's gone */
+    pub async fn clear(slug: &str, conn: &mut PooledConnection) -> Result<()> {
+        conn.del::<_, ()>(Self::key(slug)).await?;
+        conn.del::<_, ()>(Self::visitors_key(slug)).await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Pool, RedirectStatus};
+
+    #[test]
+    fn values_are_normalized() {
+        assert_eq!(normalize("  curl/8.0\n\u{7}", AGENT_LENGTH), "curl/8.0");
+        assert_eq!(
+            normalize(&"a".repeat(500), AGENT_LENGTH).len(),
+            AGENT_LENGTH
+        );
+    }
+
+    #[rocket::async_test]
+    async fn distinct_values_are_capped() {
+        let (pool, _) = Pool::memory();
+        let mut conn = pool.get();
+        let record = Record::url(
+            Absolute::parse_owned(String::from("https://example.com")).unwrap(),
+            RedirectStatus::default(),
+            String::from("slug"),
+            None,
+            None,
+        );
+
+        let total = DISTINCT_LIMIT + 5;
+        for i in 0..total {
+            let visit = Visit {
+                address: None,
+                referrer: Some(format!("{}.example", i)),
+                agent: (i % 2 == 0).then(|| String::from("curl/8.0")),
+            };
+            Analytics::click(&record, &visit, &mut conn).await.unwrap();
+        }
+
+        let analytics = Analytics::fetch("slug", &mut conn).await.unwrap();
+        assert_eq!(analytics.clicks, total);
+        assert_eq!(analytics.referrers.len() as u64, DISTINCT_LIMIT + 1);
+        assert_eq!(analytics.referrers[OTHERS], 5);
+        assert_eq!(analytics.referrers["0.example"], 1);
+        assert_eq!(analytics.agents.len(), 1);
+        assert_eq!(analytics.agents["curl/8.0"], total / 2 + 1);
+    }
+}
@@ -0,0 +1,178 @@
+use redis::AsyncCommands;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use tokio::io::AsyncReadExt;
+
+use super::{target, Error, PooledConnection, Result, BLOCKLIST_PREFIX};
+
+/** The kinds of blocklists, checked upon the creation of records */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Blocklist {
+    /** Hex-encoded SHA-256 hashes of the content of files and pastes */
+    Hashes,
+    /** Domains of url redirects, including their subdomains */
+    Domains,
+    /** Regular expressions matched against the content of pastes */
+    Patterns,
+}
+
+impl<'a> rocket::request::FromParam<'a> for Blocklist {
+    type Error = &'a str;
+
+    fn from_param(param: &'a str) -> std::result::Result<Self, Self::Error> {
+        match param {
+            "hashes" => Ok(Blocklist::Hashes),
+            "domains" => Ok(Blocklist::Domains),
+            "patterns" => Ok(Blocklist::Patterns),
+            _ => Err(param),
+        }
+    }
+}
+
+impl Blocklist {
+    #[inline]
+    fn key(self) -> String {
+        [
+            BLOCKLIST_PREFIX,
+            match self {
+                Blocklist::Hashes => "hashes",
+                Blocklist::Domains => "domains",
+                Blocklist::Patterns => "patterns",
+            },
+        ]
+        .concat()
+    }
+
+    /** Normalize the `entry` for storage, ensuring it's valid for the [`Blocklist`] */
+    fn normalize(self, entry: &str) -> Result<String> {
+        let entry = entry.trim();
+
+        match self {
+            Blocklist::Hashes if entry.len() == 64 && hex::decode(entry).is_ok() => {
+                Ok(entry.to_lowercase())
+            }
+            Blocklist::Hashes => Err(Error::BadRequest(format!(
+                "`{}` is not a hex-encoded SHA-256 hash",
+                entry
+            ))),
+            Blocklist::Domains => Ok(entry.trim_end_matches('.').to_lowercase()),
+            Blocklist::Patterns => Regex::new(entry)
+                .map(|_| entry.to_string())
+                .map_err(|err| Error::BadRequest(err.to_string())),
+        }
+    }
+
+    /** List the entries of the [`Blocklist`] */
+    pub async fn entries(self, conn: &mut PooledConnection) -> Result<Vec<String>> {
+        let mut entries: Vec<String> = conn.smembers(self.key()).await?;
+        entries.sort();
+
+        Ok(entries)
+    }
+
+    /** Add the `entries` to the [`Blocklist`], after validating all of them */
+    pub async fn add(self, entries: &[String], conn: &mut PooledConnection) -> Result<()> {
+        let entries = entries
+            .iter()
+            .map(|entry| self.normalize(entry))
+            .collect::<Result<Vec<_>>>()?;
+
+        if !entries.is_empty() {
+            conn.sadd::<_, _, ()>(self.key(), entries).await?;
+        }
+
+        Ok(())
+    }
+
+    /** Remove the `entries` from the [`Blocklist`] */
+    pub async fn remove(self, entries: &[String], conn: &mut PooledConnection) -> Result<()> {
+        let entries = entries
+            .iter()
+            .map(|entry| self.normalize(entry))
+            .collect::<Result<Vec<_>>>()?;
+
+        if !entries.is_empty() {
+            conn.srem::<_, _, ()>(self.key(), entries).await?;
+        }
+
+        Ok(())
+    }
+
+    /** Compute the hex-encoded SHA-256 hash of the `data` */
+    #[inline]
+    pub fn hash(data: &[u8]) -> String {
+        hex::encode(Sha256::digest(data))
+    }
+
+    /** Compute the hex-encoded SHA-256 hash of the file at `path`, without loading it whole */
+    pub async fn hash_file(path: &Path) -> Result<String> {
+        let mut file = tokio::fs::File::open(path).await?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0; 64 * 1024];
+
+        loop {
+            match file.read(&mut buffer).await? {
+                0 => break,
+                count => hasher.update(&buffer[..count]),
+            }
+        }
+
+        Ok(hex::encode(hasher.finalize()))
+    }
+
+    /** Ensure the content `hash` is not blocked */
+    pub async fn check_hash(hash: &str, conn: &mut PooledConnection) -> Result<()> {
+        if conn.sismember(Blocklist::Hashes.key(), hash).await? {
+            return Err(Error::Blocked(String::from(
+                "this content has been taken down",
+            )));
+        }
+
+        Ok(())
+    }
+
+    /** Ensure the content of the file at `path` is not blocked */
+    #[inline]
+    pub async fn check_file(path: &Path, conn: &mut PooledConnection) -> Result<()> {
+        Self::check_hash(&Self::hash_file(path).await?, conn).await
+    }
+
+    /** Ensure the `host` nor any of it's parent domains are blocked */
+    pub async fn check_domain(host: &str, conn: &mut PooledConnection) -> Result<()> {
+        let host = host.trim_end_matches('.').to_lowercase();
+
+        for domain in target::domains(&host) {
+            if conn.sismember(Blocklist::Domains.key(), domain).await? {
+                return Err(Error::Blocked(format!(
+                    "the domain `{}` is blocked",
+                    domain
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /** Ensure the paste's `body` does not match any of the blocked patterns */
+    pub async fn check_paste(body: &str, conn: &mut PooledConnection) -> Result<()> {
+        for pattern in Blocklist::Patterns.entries(&mut *conn).await? {
+            /* Patterns are validated when added, an invalid one can only come from a manual edit */
+            match Regex::new(&pattern) {
+                Ok(regex) if regex.is_match(body) => {
+                    return Err(Error::Blocked(String::from(
+                        "this paste matches a blocked pattern",
+                    )))
+                }
+                Ok(_) => (),
+                Err(err) => tracing::warn!(
+                    "Ignoring the invalid blocked pattern `{}`: {}",
+                    pattern,
+                    err
+                ),
+            }
+        }
+
+        Self::check_hash(&Self::hash(body.as_bytes()), conn).await
+    }
+}
@@ -1,6 +1,8 @@
 use std::borrow::Cow;
 use thiserror::Error;
 
+use super::{RateLimit, Reason};
+
 /** Represent's an application's error */
 #[derive(Error, Debug)]
 pub enum Error<'s> {
@@ -8,6 +10,18 @@ pub enum Error<'s> {
     #[error("Couldn't find the record identified with the slug `{0}`")]
     NotFound(String),
 
+    #[error("The record identified with the slug `{0}` is gone")]
+    Gone(String, Reason),
+
+    #[error("The record identified with the slug `{0}` was taken down ({1})")]
+    TakenDown(String, String),
+
+    #[error("The content is blocked ({0})")]
+    Blocked(String),
+
+    #[error("The request is invalid ({0})")]
+    BadRequest(String),
+
     #[error("File upload failed ({0})")]
     FileUpload(String),
 
@@ -17,6 +31,36 @@ pub enum Error<'s> {
     #[error("Url record creation failed ({0})")]
     UrlCreation(String),
 
+    #[error("The custom slug `{0}` was refused ({1})")]
+    InvalidSlug(String, String),
+
+    #[error("The slug `{0}` is already taken")]
+    SlugTaken(String),
+
+    #[error("Authentication is required ({0})")]
+    Unauthorized(String),
+
+    #[error("This action is not allowed ({0})")]
+    Forbidden(String),
+
+    #[error("Too many requests ({0})")]
+    TooManyRequests(String, RateLimit),
+
+    #[error("The payload is too large ({0})")]
+    PayloadTooLarge(String),
+
+    #[error("The upload offset `{1}` does not match the expected offset `{0}`")]
+    UploadOffset(u64, u64),
+
+    #[error("The upload `{0}` is already being appended to")]
+    UploadLocked(String),
+
+    #[error("Unsupported protocol version ({0})")]
+    UnsupportedVersion(String),
+
+    #[error("Unsupported media type ({0})")]
+    UnsupportedMediaType(String),
+
     /* 5xx errors */
     #[error("There was an infortuate error in the application's logic ({0})")]
     Intrinsics(Cow<'s, str>),
@@ -30,6 +74,9 @@ pub enum Error<'s> {
     #[error("I/O error: {0}")]
     IO(#[from] tokio::io::Error),
 
+    #[error("Not enough storage left on the server ({0})")]
+    InsufficientStorage(String),
+
     #[error("Could not query the Redis server ({0})")]
     Redis(#[from] redis::RedisError),
 
@@ -37,36 +84,116 @@ pub enum Error<'s> {
     SerDe(#[from] bincode::Error),
 }
 
+impl<'s> Error<'s> {
+    /** Get the HTTP [`Status`](rocket::http::Status) associated with the [`Error`] */
+    pub fn status(&self) -> rocket::http::Status {
+        use rocket::http::Status;
+
+        match self {
+            /* 4xx errors */
+            Error::NotFound(_) => Status::NotFound,
+            Error::Gone(..) => Status::Gone,
+            Error::TakenDown(..) | Error::Blocked(_) => Status::UnavailableForLegalReasons,
+            Error::BadRequest(_) => Status::BadRequest,
+            Error::FileUpload(_)
+            | Error::PasteCreation(_)
+            | Error::UrlCreation(_)
+            | Error::InvalidSlug(..) => Status::UnprocessableEntity,
+            Error::Unauthorized(_) => Status::Unauthorized,
+            Error::Forbidden(_) => Status::Forbidden,
+            Error::TooManyRequests(..) => Status::TooManyRequests,
+            Error::PayloadTooLarge(_) => Status::PayloadTooLarge,
+            Error::UploadOffset(..) | Error::SlugTaken(_) => Status::Conflict,
+            Error::UploadLocked(_) => Status::Locked,
+            Error::UnsupportedVersion(_) => Status::PreconditionFailed,
+            Error::UnsupportedMediaType(_) => Status::UnsupportedMediaType,
+
+            /* 5xx errors */
+            Error::InsufficientStorage(_) => Status::InsufficientStorage,
+            Error::Redis(_) => Status::ServiceUnavailable,
+            Error::Config(_)
+            | Error::IO(_)
+            | Error::SerDe(_)
+            | Error::Templating(_)
+            | Error::Intrinsics(_) => Status::InternalServerError,
+        }
+    }
+
+    /** Render the [`Error`] as an HTML page, for the browsers */
+    fn html(&self, status: rocket::http::Status) -> Result<String, liquid::Error> {
+        let template = liquid::ParserBuilder::with_stdlib()
+            .build()?
+            .parse(include_str!("../../ui/error.html"))?;
+
+        let globals = liquid::object!({
+            "status": status.to_string(),
+            "message": self.to_string(),
+            "reason": match self {
+                Error::Gone(_, reason) => Some(reason.to_string()),
+                Error::TakenDown(..) => Some(Reason::TakenDown.to_string()),
+                _ => None,
+            },
+        });
+
+        template.render(&globals)
+    }
+}
+
 impl<'r, 'o: 'r> rocket::response::Responder<'r, 'o> for Error<'o> {
     fn respond_to(self, req: &'r rocket::request::Request<'_>) -> rocket::response::Result<'o> {
-        use rocket::{http::Status, response::status, serde::json};
+        use rocket::{
+            response::{content, status},
+            serde::json,
+        };
         use serde::Serialize;
 
         #[derive(Serialize)]
         struct ErrorResponse {
             message: String,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            reason: Option<Reason>,
         }
-        let error = json::Json(ErrorResponse {
-            message: self.to_string(),
-        });
 
-        Ok(match self {
-            /* 4xx errors */
-            Error::NotFound(_) => status::NotFound(error).respond_to(req)?,
-            Error::FileUpload(_) | Error::PasteCreation(_) | Error::UrlCreation(_) => {
-                status::Custom(Status::UnprocessableEntity, error).respond_to(req)?
-            }
+        let status = self.status();
+        let html = req
+            .accept()
+            .map(|accept| accept.preferred().media_type().is_html())
+            .unwrap_or(false);
 
-            /* 5xx errors */
-            Error::Redis(_) => status::Custom(Status::ServiceUnavailable, error).respond_to(req)?,
-            Error::Config(_)
-            | Error::IO(_)
-            | Error::SerDe(_)
-            | Error::Templating(_)
-            | Error::Intrinsics(_) => {
-                status::Custom(Status::InternalServerError, error).respond_to(req)?
+        let page = if html { self.html(status).ok() } else { None };
+
+        let mut response = match page {
+            /* Browsers get a human-readable page */
+            Some(page) => status::Custom(status, content::Html(page)).respond_to(req)?,
+            /* Everyone else gets a machine-readable body */
+            None => status::Custom(
+                status,
+                json::Json(ErrorResponse {
+                    message: self.to_string(),
+                    reason: match self {
+                        Error::Gone(_, reason) => Some(reason),
+                        Error::TakenDown(..) => Some(Reason::TakenDown),
+                        _ => None,
+                    },
+                }),
+            )
+            .respond_to(req)?,
+        };
+
+        /* Advertise the supported tus protocol version when it is not satisfied */
+        if let Error::UnsupportedVersion(_) = self {
+            response.set_raw_header("Tus-Version", "1.0.0");
+        }
+
+        /* Advertise when the exceeded quota will be available again */
+        if let Error::TooManyRequests(_, limit) = self {
+            response.set_raw_header("Retry-After", limit.reset.to_string());
+            for header in limit.headers() {
+                response.set_header(header);
             }
-        })
+        }
+
+        Ok(response)
     }
 }
 
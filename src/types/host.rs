@@ -1,3 +1,4 @@
+use ipnet::IpNet;
 use rocket::{
     http::{
         self,
@@ -6,25 +7,159 @@ use rocket::{
     outcome::Outcome,
     request::{FromRequest, Request},
 };
+use std::net::IpAddr;
 
-/** Get the `Host` header from the [`Request`] and wrap it */
+use super::Error;
+
+/** The forwarding informations set by the reverse proxies, only honored when the peer is a trusted proxy */
+#[derive(Debug, Default)]
+struct Forwarded<'r> {
+    /** The chain of addresses, from the original client to the last proxy */
+    chain: Vec<IpAddr>,
+    host: Option<&'r str>,
+    proto: Option<&'r str>,
+}
+
+impl<'r> Forwarded<'r> {
+    /** Extract the forwarding informations of the `request`, either from the standard `Forwarded` header
+     * (RFC 7239) or from the `X-Forwarded-*` headers */
+    fn from(request: &'r Request<'_>, trusted: &[IpNet]) -> Self {
+        let peer = match request.remote() {
+            Some(peer) => peer.ip(),
+            None => return Self::default(),
+        };
+
+        /* Anyone may set these headers, so they only mean something when set by our proxies */
+        if !trusted.iter().any(|net| net.contains(&peer)) {
+            return Self::default();
+        }
+
+        let headers = request.headers();
+
+        match headers.get_one("Forwarded") {
+            Some(_) => {
+                let elements: Vec<_> = headers
+                    .get("Forwarded")
+                    .flat_map(|value| value.split(','))
+                    .map(Element::parse)
+                    .collect();
+
+                /* Each proxy appends it's own element, so the ones before the element of the first trusted hop were
+                 * written by the client, walk them back like the chain of addresses */
+                let mut first = elements.len().saturating_sub(1);
+                while first > 0
+                    && elements[first]
+                        .node
+                        .is_some_and(|node| trusted.iter().any(|net| net.contains(&node)))
+                {
+                    first -= 1;
+                }
+                let hops = &elements[first..];
+
+                Self {
+                    chain: elements.iter().filter_map(|element| element.node).collect(),
+                    host: hops.iter().find_map(|element| element.host),
+                    proto: hops.iter().find_map(|element| element.proto),
+                }
+            }
+            None => Self {
+                chain: headers
+                    .get("X-Forwarded-For")
+                    .flat_map(|value| value.split(','))
+                    .filter_map(|address| address.trim().parse().ok())
+                    .collect(),
+                /* The last value was set by our proxy, the previous ones may come from the client */
+                host: Self::last(headers.get("X-Forwarded-Host")),
+                proto: Self::last(headers.get("X-Forwarded-Proto")),
+            },
+        }
+    }
+
+    /** Get the last of the comma-separated `values` of a header, across all it's occurences */
+    fn last(values: impl Iterator<Item = &'r str>) -> Option<&'r str> {
+        values
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .last()
+    }
+
+    /** Parse a node of the `Forwarded` header, like `192.0.2.43`, `192.0.2.43:47011` or `[2001:db8::1]:4711` */
+    fn node(node: &str) -> Option<IpAddr> {
+        if let Some(node) = node.strip_prefix('[') {
+            return node.split(']').next()?.parse().ok();
+        }
+
+        node.split(':').next()?.parse().ok()
+    }
+}
+
+/** An element of the `Forwarded` header, appended by a single proxy */
+#[derive(Debug, Default)]
+struct Element<'r> {
+    /** The address of the node which connected to the proxy */
+    node: Option<IpAddr>,
+    host: Option<&'r str>,
+    proto: Option<&'r str>,
+}
+
+impl<'r> Element<'r> {
+    /** Parse an `element` of the `Forwarded` header, like `for=192.0.2.43;proto=https;host=example.com` */
+    fn parse(element: &'r str) -> Self {
+        let mut parsed = Self::default();
+
+        for pair in element.split(';') {
+            let mut pair = pair.trim().splitn(2, '=');
+            let (name, value) = match (pair.next(), pair.next()) {
+                (Some(name), Some(value)) => (name, value.trim_matches('"')),
+                _ => continue,
+            };
+
+            match name.to_ascii_lowercase().as_str() {
+                "for" => parsed.node = Forwarded::node(value),
+                "host" => parsed.host = Some(value),
+                "proto" => parsed.proto = Some(value),
+                _ => (),
+            }
+        }
+
+        parsed
+    }
+}
+
+/** Get the [`Config`](crate::Config) managed by the [`Request`]'s [`Rocket`](rocket::Rocket) */
+fn config<'r>(request: &'r Request<'_>) -> Option<&'r crate::Config> {
+    request.rocket().state::<crate::Config>()
+}
+
+/** Get the public base url from the configuration, or the `Host` header from the [`Request`], and wrap it */
 pub struct HostBase<'r>(uri::Reference<'r>);
 
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for HostBase<'r> {
-    type Error = ();
+    type Error = Error<'static>;
 
     async fn from_request(request: &'r Request<'_>) -> rocket::request::Outcome<Self, Self::Error> {
         const DEFAULT_PROTO: &str = "http";
 
-        let hostname = request
-            .headers()
-            .get_one("X-Forwarded-Host")
-            .or_else(|| request.headers().get_one("Host"));
-        let proto = request
-            .headers()
-            .get_one("X-Forwarded-Proto")
-            .unwrap_or(DEFAULT_PROTO);
+        let config = config(request);
+
+        /* A configured public base url always wins over the headers, it was validated upon startup */
+        match config.map(crate::Config::base_url) {
+            Some(Ok(Some(base))) => return Outcome::Success(Self(base.into())),
+            Some(Err(err)) => return Outcome::Failure((http::Status::InternalServerError, err)),
+            _ => (),
+        }
+
+        let forwarded = Forwarded::from(
+            request,
+            config
+                .map(|config| &config.trusted_proxies[..])
+                .unwrap_or_default(),
+        );
+
+        let hostname = forwarded.host.or_else(|| request.headers().get_one("Host"));
+        let proto = forwarded.proto.unwrap_or(DEFAULT_PROTO);
 
         tracing::trace!(
             "Received the following hostname `{:?}` and protocol `{}`",
@@ -40,7 +175,10 @@ impl<'r> FromRequest<'r> for HostBase<'r> {
                 })
             }) {
             Some(Ok(Ok(base))) => Outcome::Success(Self(base.into_normalized())),
-            _ => Outcome::Failure((http::Status::HttpVersionNotSupported, ())),
+            _ => Outcome::Failure((
+                http::Status::BadRequest,
+                Error::BadRequest(String::from("the `Host` header is missing or malformed")),
+            )),
         }
     }
 }
@@ -53,9 +191,237 @@ impl<'r> HostBase<'r> {
             .into_normalized()
     }
 
+    /** Access the host of the [`HostBase`], without it's port */
+    #[inline]
+    pub fn host(&self) -> Option<&str> {
+        self.0.authority().map(|authority| authority.host())
+    }
+
     /** Retrieve the inner [`uri::Reference`] from the [`HostBase`] */
     #[inline]
     pub fn into_inner(self) -> uri::Reference<'r> {
         self.0
     }
 }
+
+/** Get the client's IP address from the [`Request`], honoring the same proxy headers as [`HostBase`] */
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIp(IpAddr);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientIp {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> rocket::request::Outcome<Self, Self::Error> {
+        let trusted = config(request)
+            .map(|config| &config.trusted_proxies[..])
+            .unwrap_or_default();
+        let forwarded = Forwarded::from(request, trusted);
+
+        tracing::trace!(
+            "Received the following forwarded chain `{:?}`",
+            forwarded.chain
+        );
+
+        /* Walk back the chain from our peer, the client is the first address not being one of our proxies */
+        let client = request.remote().map(|peer| {
+            let mut client = peer.ip();
+
+            for address in forwarded.chain.iter().rev() {
+                if !trusted.iter().any(|net| net.contains(&client)) {
+                    break;
+                }
+
+                client = *address;
+            }
+
+            client
+        });
+
+        match client {
+            Some(address) => Outcome::Success(Self(address)),
+            None => Outcome::Failure((http::Status::BadRequest, ())),
+        }
+    }
+}
+
+impl ClientIp {
+    /** Retrieve the inner [`IpAddr`] from the [`ClientIp`] */
+    #[inline]
+    pub const fn into_inner(self) -> IpAddr {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::{http::Header, local::asynchronous::Client, uri};
+    use std::net::SocketAddr;
+
+    /** Build a client whose configuration trusts the proxies of `10.0.0.0/8`, and has the `public_base_url` */
+    async fn client(public_base_url: Option<&str>) -> Client {
+        let config = crate::Config {
+            trusted_proxies: vec!["10.0.0.0/8".parse().unwrap()],
+            public_base_url: public_base_url.map(String::from),
+            ..crate::Config::default()
+        };
+
+        Client::untracked(rocket::build().manage(config))
+            .await
+            .unwrap()
+    }
+
+    fn peer(address: &str) -> SocketAddr {
+        SocketAddr::new(address.parse().unwrap(), 4711)
+    }
+
+    #[rocket::async_test]
+    async fn untrusted_peers_are_ignored() {
+        let client = client(None).await;
+        let request = client
+            .get("/")
+            .remote(peer("203.0.113.7"))
+            .header(Header::new("X-Forwarded-For", "198.51.100.1"))
+            .header(Header::new("X-Forwarded-Host", "evil.example"))
+            .header(Header::new(
+                "Forwarded",
+                "for=198.51.100.1;host=evil.example",
+            ))
+            .header(Header::new("Host", "shrekd.example"));
+
+        let ip = ClientIp::from_request(request.inner()).await.unwrap();
+        assert_eq!(ip.into_inner(), "203.0.113.7".parse::<IpAddr>().unwrap());
+
+        let host = HostBase::from_request(request.inner()).await.unwrap();
+        assert_eq!(host.into_inner().to_string(), "http://shrekd.example");
+    }
+
+    #[rocket::async_test]
+    async fn trusted_peers_forward_the_client() {
+        let client = client(None).await;
+        let request = client
+            .get("/")
+            .remote(peer("10.0.0.1"))
+            .header(Header::new(
+                "X-Forwarded-For",
+                "192.0.2.1, 198.51.100.1, 10.0.0.2",
+            ))
+            .header(Header::new("X-Forwarded-Host", "shrekd.example"))
+            .header(Header::new("X-Forwarded-Proto", "https"))
+            .header(Header::new("Host", "internal:8000"));
+
+        /* The spoofable addresses before the first untrusted one are not the client */
+        let ip = ClientIp::from_request(request.inner()).await.unwrap();
+        assert_eq!(ip.into_inner(), "198.51.100.1".parse::<IpAddr>().unwrap());
+
+        let host = HostBase::from_request(request.inner()).await.unwrap();
+        assert_eq!(host.into_inner().to_string(), "https://shrekd.example");
+    }
+
+    #[rocket::async_test]
+    async fn standard_header_wins_over_the_legacy_ones() {
+        let client = client(None).await;
+        let request = client
+            .get("/")
+            .remote(peer("10.0.0.1"))
+            .header(Header::new(
+                "Forwarded",
+                "for=\"[2001:db8::1]:4711\";proto=https;host=shrekd.example, for=10.0.0.2",
+            ))
+            .header(Header::new("X-Forwarded-For", "198.51.100.1"))
+            .header(Header::new("X-Forwarded-Host", "evil.example"));
+
+        let forwarded = Forwarded::from(request.inner(), &["10.0.0.0/8".parse().unwrap()]);
+        assert_eq!(
+            forwarded.chain,
+            vec![
+                "2001:db8::1".parse::<IpAddr>().unwrap(),
+                "10.0.0.2".parse().unwrap()
+            ]
+        );
+        assert_eq!(forwarded.host, Some("shrekd.example"));
+        assert_eq!(forwarded.proto, Some("https"));
+
+        let ip = ClientIp::from_request(request.inner()).await.unwrap();
+        assert_eq!(ip.into_inner(), "2001:db8::1".parse::<IpAddr>().unwrap());
+    }
+
+    #[rocket::async_test]
+    async fn client_elements_are_ignored() {
+        let client = client(None).await;
+        let request = client.get("/").remote(peer("10.0.0.1")).header(Header::new(
+            "Forwarded",
+            "host=evil.example;proto=http, host=shrekd.example;proto=https",
+        ));
+
+        let host = HostBase::from_request(request.inner()).await.unwrap();
+        assert_eq!(host.into_inner().to_string(), "https://shrekd.example");
+
+        /* The elements of the trusted hops are walked back up to the first one */
+        let request = client
+            .get("/")
+            .remote(peer("10.0.0.1"))
+            .header(Header::new("Forwarded", "host=evil.example"))
+            .header(Header::new(
+                "Forwarded",
+                "for=198.51.100.1;host=shrekd.example, for=10.0.0.2;host=internal:8000",
+            ));
+
+        let host = HostBase::from_request(request.inner()).await.unwrap();
+        assert_eq!(host.into_inner().to_string(), "http://shrekd.example");
+    }
+
+    #[rocket::async_test]
+    async fn last_legacy_values_are_used() {
+        let client = client(None).await;
+        let request = client
+            .get("/")
+            .remote(peer("10.0.0.1"))
+            .header(Header::new(
+                "X-Forwarded-Host",
+                "evil.example, shrekd.example",
+            ))
+            .header(Header::new("X-Forwarded-Proto", "http"))
+            .header(Header::new("X-Forwarded-Proto", "https"));
+
+        let host = HostBase::from_request(request.inner()).await.unwrap();
+        assert_eq!(host.into_inner().to_string(), "https://shrekd.example");
+    }
+
+    #[rocket::async_test]
+    async fn public_base_url_wins_over_the_headers() {
+        let client = client(Some("https://shrekd.example/")).await;
+        let request = client
+            .get("/")
+            .remote(peer("10.0.0.1"))
+            .header(Header::new("X-Forwarded-Host", "evil.example"));
+
+        let host = HostBase::from_request(request.inner()).await.unwrap();
+        assert_eq!(
+            host.with(uri!("/slug")).to_string(),
+            "https://shrekd.example/slug"
+        );
+    }
+
+    #[test]
+    fn relative_public_base_urls_are_refused() {
+        for base in [
+            "shrekd.example",
+            "/shrekd",
+            "ftp://shrekd.example",
+            "https://shrekd.example?a=b",
+        ] {
+            let config = crate::Config {
+                public_base_url: Some(String::from(base)),
+                ..crate::Config::default()
+            };
+
+            assert!(
+                matches!(config.base_url(), Err(Error::Config(_))),
+                "{}",
+                base
+            );
+        }
+    }
+}
@@ -0,0 +1,345 @@
+use redis::AsyncCommands;
+use rocket::{
+    http::Status,
+    outcome::Outcome,
+    request::{FromRequest, Request},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{ClientIp, Error, Pool, RecordSettings, RecordType, Result, Throttle, KEY_PREFIX};
+
+/** The limits applied to the records created with an API key, or anonymously */
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct KeyPolicy {
+    /** Max file size, in bytes, capped by the server's own */
+    pub max_file_size: Option<u64>,
+    /** Types of records that may be created */
+    pub types: Vec<RecordType>,
+    /** Max lifetime of the records, in seconds, capped by the server's own */
+    pub max_age: Option<u64>,
+    /** Whether the `Custom-Slug` header may be used */
+    pub custom_slug: bool,
+    /** Max number of bytes uploaded over the last day */
+    pub max_bytes_per_day: Option<u64>,
+    /** Max number of records created over the last hour */
+    pub max_records_per_hour: Option<u64>,
+    /** Max number of records alive at the same time */
+    pub max_active_records: Option<u64>,
+}
+
+impl Default for KeyPolicy {
+    fn default() -> Self {
+        KeyPolicy {
+            max_file_size: None,
+            types: vec![RecordType::File, RecordType::Paste, RecordType::Url],
+            max_age: None,
+            custom_slug: true,
+            max_bytes_per_day: None,
+            max_records_per_hour: None,
+            max_active_records: None,
+        }
+    }
+}
+
+impl KeyPolicy {
+    /** The conservative limits of the anonymous creations, which can't be traced back to anyone but their IP */
+    pub fn anonymous() -> Self {
+        KeyPolicy {
+            max_file_size: Some(10_000_000),
+            custom_slug: false,
+            max_bytes_per_day: Some(100_000_000),
+            max_records_per_hour: Some(20),
+            max_active_records: Some(50),
+            ..KeyPolicy::default()
+        }
+    }
+}
+
+/** An API key, identified by the hex-encoded SHA-256 `hash` of it's secret value */
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ApiKey {
+    /** Human-readable name of the key's holder */
+    pub name: String,
+    /** Hex-encoded SHA-256 hash of the key */
+    #[serde(default)]
+    pub hash: String,
+    /** Limits of the records created with the key */
+    #[serde(flatten)]
+    pub policy: KeyPolicy,
+}
+
+impl ApiKey {
+    /** Compute the hex-encoded SHA-256 hash of the `key`, as stored in the configuration and Redis */
+    #[inline]
+    pub fn hash(key: &str) -> String {
+        hex::encode(Sha256::digest(key.as_bytes()))
+    }
+
+    /** Find the [`ApiKey`] matching the `key`, from the configuration first then the Redis server */
+    pub async fn find(key: &str, config: &crate::Config, pool: &Pool) -> Result<Option<ApiKey>> {
+        let hash = Self::hash(key);
+
+        if let Some(key) = config.api_keys.iter().find(|api_key| api_key.hash == hash) {
+            return Ok(Some(key.clone()));
+        }
+
+        /* Keys in Redis are stored as JSON, so they can be provisioned by hand */
+        let key: Option<String> = pool.get().get([KEY_PREFIX, &hash].concat()).await?;
+
+        key.map(|key| {
+            rocket::serde::json::serde_json::from_str::<ApiKey>(&key)
+                .map(|key| ApiKey { hash, ..key })
+                .map_err(|err| Error::Intrinsics(err.to_string().into()))
+        })
+        .transpose()
+    }
+}
+
+/** The identity of the client creating a record, either an [`ApiKey`] or anonymous */
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Identity {
+    name: Option<String>,
+    /** The subject of the quotas, the API key or the client's IP address for anonymous clients */
+    subject: String,
+    policy: KeyPolicy,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Identity {
+    type Error = Error<'static>;
+
+    async fn from_request(request: &'r Request<'_>) -> rocket::request::Outcome<Self, Self::Error> {
+        let rocket = request.rocket();
+        let (config, pool) = match (rocket.state::<crate::Config>(), rocket.state::<Pool>()) {
+            (Some(config), Some(pool)) => (config, pool),
+            _ => {
+                return Outcome::Failure((
+                    Status::InternalServerError,
+                    Error::Intrinsics("The application's state is missing".into()),
+                ))
+            }
+        };
+
+        /* The key is either provided as a bearer token or in it's own header */
+        let key = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .or_else(|| request.headers().get_one("Api-Key"));
+
+        match key {
+            Some(key) => match ApiKey::find(key.trim(), config, pool).await {
+                Ok(Some(key)) => {
+                    tracing::debug!("Authenticated the request as `{}`", key.name);
+
+                    Outcome::Success(Self {
+                        subject: format!("key:{}", key.name),
+                        name: Some(key.name),
+                        policy: key.policy,
+                    })
+                }
+                Ok(None) => Outcome::Failure((
+                    Status::Unauthorized,
+                    Error::Unauthorized("the provided API key is unknown".into()),
+                )),
+                Err(err) => Outcome::Failure((err.status(), err)),
+            },
+            None if config.allow_anonymous => match request.guard::<ClientIp>().await {
+                Outcome::Success(ip) => Outcome::Success(Self {
+                    name: None,
+                    subject: format!("ip:{}", ip.into_inner()),
+                    policy: config.anonymous.clone(),
+                }),
+                _ => Outcome::Failure((
+                    Status::BadRequest,
+                    Error::Unauthorized("the client's address could not be determined".into()),
+                )),
+            },
+            None => Outcome::Failure((
+                Status::Unauthorized,
+                Error::Unauthorized("an API key is required to create records".into()),
+            )),
+        }
+    }
+}
+
+impl Identity {
+    /** Access the name of the [`ApiKey`]'s holder, if any */
+    #[inline]
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /** Get the [`Throttle`] enforcing the rolling quotas of the [`Identity`] */
+    #[inline]
+    pub fn throttle(&self) -> Throttle<'_> {
+        Throttle::new(&self.subject, &self.policy)
+    }
+
+    /** Ensure the [`Identity`] may create a record of the `kind`, with the provided `settings` */
+    pub fn authorize(&self, kind: RecordType, settings: &RecordSettings) -> Result<()> {
+        if !self.policy.types.contains(&kind) {
+            return Err(Error::Forbidden(format!(
+                "the creation of `{}` records is not allowed",
+                kind
+            )));
+        }
+
+        if settings.custom_slug().is_some() && !self.policy.custom_slug {
+            return Err(Error::Forbidden(
+                "the `Custom-Slug` header is not allowed".into(),
+            ));
+        }
+
+        /* Only the owner may mint the signed links, so there must be one */
+        if settings.signed() && self.name.is_none() {
+            return Err(Error::Unauthorized(
+                "an API key is required to create records requiring a signature".into(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /** Compute the max file size of the [`Identity`], capped by the server's `max_file_size` */
+    #[inline]
+    pub fn max_file_size(&self, max_file_size: u64) -> u64 {
+        self.policy
+            .max_file_size
+            .map_or(max_file_size, |max| max.min(max_file_size))
+    }
+
+    /** Compute the max lifetime of the [`Identity`]'s records, capped by the server's `max_age` */
+    #[inline]
+    pub fn max_age(&self, max_age: u64) -> u64 {
+        self.policy.max_age.map_or(max_age, |max| max.min(max_age))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::Header;
+    use rocket::serde::json;
+
+    use super::*;
+
+    fn anonymous(policy: KeyPolicy) -> Identity {
+        Identity {
+            name: None,
+            subject: String::from("ip:203.0.113.7"),
+            policy,
+        }
+    }
+
+    fn settings(custom_slug: Option<&str>, signed: bool) -> RecordSettings {
+        json::from_str(
+            &json::json!({
+                "custom_slug": custom_slug,
+                "burn_after_reading": false,
+                "require_signature": signed,
+            })
+            .to_string(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn anonymous_creations_are_limited_by_default() {
+        let policy = crate::Config::default().anonymous;
+        assert_eq!(policy.max_file_size, Some(10_000_000));
+        assert_eq!(policy.max_bytes_per_day, Some(100_000_000));
+        assert_eq!(policy.max_records_per_hour, Some(20));
+        assert_eq!(policy.max_active_records, Some(50));
+        assert_eq!(policy.max_age, None);
+        assert!(!policy.custom_slug);
+
+        let identity = anonymous(policy);
+        assert!(identity
+            .authorize(RecordType::Url, &settings(None, false))
+            .is_ok());
+        assert!(identity
+            .authorize(RecordType::Paste, &settings(Some("custom"), false))
+            .is_err());
+        assert!(identity
+            .authorize(RecordType::File, &settings(None, true))
+            .is_err());
+
+        /* The policies can only tighten the server's own limits */
+        assert_eq!(identity.max_file_size(1_000), 1_000);
+        assert_eq!(identity.max_file_size(u64::MAX), 10_000_000);
+        assert_eq!(identity.max_age(60), 60);
+    }
+
+    #[rocket::async_test]
+    async fn keys_are_found_in_the_configuration_then_redis() {
+        let (pool, _) = Pool::memory();
+        let config = crate::Config {
+            api_keys: vec![ApiKey {
+                name: String::from("configured"),
+                hash: ApiKey::hash("first"),
+                policy: KeyPolicy::default(),
+            }],
+            ..crate::Config::default()
+        };
+        pool.get()
+            .set::<_, _, ()>(
+                [KEY_PREFIX, &ApiKey::hash("second")].concat(),
+                r#"{ "name": "provisioned", "types": ["paste"], "max_age": 60 }"#,
+            )
+            .await
+            .unwrap();
+
+        let first = ApiKey::find("first", &config, &pool)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(first.name, "configured");
+
+        let second = ApiKey::find("second", &config, &pool)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(second.name, "provisioned");
+        assert_eq!(second.hash, ApiKey::hash("second"));
+        assert_eq!(second.policy.types, [RecordType::Paste]);
+        assert_eq!(second.policy.max_age, Some(60));
+        assert!(second.policy.custom_slug);
+
+        assert!(ApiKey::find("third", &config, &pool)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[rocket::async_test]
+    async fn keys_are_required_without_anonymous_creations() {
+        let config = crate::Config {
+            allow_anonymous: false,
+            api_keys: vec![ApiKey {
+                name: String::from("configured"),
+                hash: ApiKey::hash("key"),
+                policy: KeyPolicy::default(),
+            }],
+            ..crate::Config::default()
+        };
+        let (client, _) = crate::api::client(config).await;
+
+        for (header, status) in [
+            (None, 401),
+            (Some(Header::new("Api-Key", "unknown")), 401),
+            (Some(Header::new("Api-Key", "key")), 200),
+            (Some(Header::new("Authorization", "Bearer key")), 200),
+        ] {
+            let mut request = client
+                .get("/api/retention?size=1&type=paste")
+                .remote("203.0.113.7:4711".parse().unwrap());
+            if let Some(header) = header {
+                request = request.header(header);
+            }
+
+            assert_eq!(request.dispatch().await.status().code, status);
+        }
+    }
+}
@@ -0,0 +1,749 @@
+/*!
+ * An in-memory stand-in for the Redis server, running the commands and the Lua scripts of the tests without one
+ */
+use redis::{Arg, Cmd, ErrorKind, Pipeline, RedisError, RedisResult, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/** The data held by a key */
+#[derive(Debug, Clone)]
+enum Data {
+    String(Vec<u8>),
+    Hash(BTreeMap<Vec<u8>, Vec<u8>>),
+    Set(BTreeSet<Vec<u8>>),
+    Sorted(BTreeMap<Vec<u8>, f64>),
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    data: Data,
+    /** Date of expiry, as a UNIX timestamp in milliseconds */
+    expiry: Option<i64>,
+}
+
+#[derive(Debug, Default)]
+struct Store {
+    entries: HashMap<Vec<u8>, Entry>,
+    /** Loaded scripts, by SHA-1 digest */
+    scripts: HashMap<String, Vec<u8>>,
+    /** Time travelled by the clock of the expiries, in milliseconds */
+    offset: i64,
+}
+
+/** A shared in-memory Redis database */
+#[derive(Debug, Clone, Default)]
+pub struct Memory(Arc<Mutex<Store>>);
+
+impl Memory {
+    /** Run a single command */
+    pub fn query(&self, cmd: &Cmd) -> RedisResult<Value> {
+        self.0.lock().unwrap().exec(&args(cmd))
+    }
+
+    /** Run the commands of a pipeline at once, so they are atomic whether the pipeline is or not */
+    pub fn query_pipeline(
+        &self,
+        pipeline: &Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisResult<Vec<Value>> {
+        let mut store = self.0.lock().unwrap();
+        let results = pipeline
+            .cmd_iter()
+            .map(|cmd| store.exec(&args(cmd)))
+            .collect::<RedisResult<Vec<_>>>()?;
+
+        /* Transactions are answered by the single reply of their `EXEC`, after the ones of `MULTI` and the commands */
+        Ok(match (offset, count) {
+            (0, _) => results,
+            _ => vec![Value::Bulk(results)],
+        })
+    }
+
+    /** Move the clock of the expiries forward by `duration` */
+    pub fn advance(&self, duration: Duration) {
+        self.0.lock().unwrap().offset += duration.as_millis() as i64;
+    }
+}
+
+/** Extract the arguments of the `cmd`, starting with it's name */
+fn args(cmd: &Cmd) -> Vec<Vec<u8>> {
+    cmd.args_iter()
+        .map(|arg| match arg {
+            Arg::Simple(arg) => arg.to_vec(),
+            Arg::Cursor => b"0".to_vec(),
+        })
+        .collect()
+}
+
+fn error(kind: ErrorKind, description: &'static str) -> RedisError {
+    RedisError::from((kind, description))
+}
+
+fn wrong_type() -> RedisError {
+    error(
+        ErrorKind::TypeError,
+        "WRONGTYPE Operation against a key holding the wrong kind of value",
+    )
+}
+
+fn text(arg: &[u8]) -> String {
+    String::from_utf8_lossy(arg).into_owned()
+}
+
+fn int(arg: &[u8]) -> RedisResult<i64> {
+    text(arg)
+        .parse()
+        .map_err(|_| error(ErrorKind::ResponseError, "value is not an integer"))
+}
+
+fn float(arg: &[u8]) -> RedisResult<f64> {
+    match text(arg).to_lowercase().as_str() {
+        "+inf" | "inf" => Ok(f64::INFINITY),
+        "-inf" => Ok(f64::NEG_INFINITY),
+        arg => arg
+            .parse()
+            .map_err(|_| error(ErrorKind::ResponseError, "value is not a valid float")),
+    }
+}
+
+/** Parse a bound of a score range, like `5`, `(5` or `+inf`, into it's value and whether it's exclusive */
+fn bound(arg: &[u8]) -> RedisResult<(f64, bool)> {
+    match arg.strip_prefix(b"(") {
+        Some(arg) => Ok((float(arg)?, true)),
+        None => Ok((float(arg)?, false)),
+    }
+}
+
+fn within(
+    score: f64,
+    (min, min_exclusive): (f64, bool),
+    (max, max_exclusive): (f64, bool),
+) -> bool {
+    (score > min || (!min_exclusive && score == min))
+        && (score < max || (!max_exclusive && score == max))
+}
+
+fn score(score: f64) -> Vec<u8> {
+    score.to_string().into_bytes()
+}
+
+/** Whether the `key` matches the glob-style `pattern`, supporting `*`, `?` and escapes */
+fn matches(pattern: &[u8], key: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => key.is_empty(),
+        Some((b'*', rest)) => (0..=key.len()).any(|skip| matches(rest, &key[skip..])),
+        Some((b'?', rest)) => !key.is_empty() && matches(rest, &key[1..]),
+        Some((b'\\', rest)) if !rest.is_empty() => {
+            key.first() == rest.first() && matches(&rest[1..], &key[1..])
+        }
+        Some((c, rest)) => key.first() == Some(c) && matches(rest, &key[1..]),
+    }
+}
+
+/** Resolve the `start` and `stop` indexes of a range, which may count from the end, over `len` elements */
+fn range(start: i64, stop: i64, len: usize) -> std::ops::Range<usize> {
+    let len = len as i64;
+    let start = if start < 0 { len + start } else { start }.max(0);
+    let stop = if stop < 0 { len + stop } else { stop }.min(len - 1);
+
+    match start <= stop {
+        true => start as usize..stop as usize + 1,
+        false => 0..0,
+    }
+}
+
+impl Store {
+    fn now(&self) -> i64 {
+        chrono::Utc::now().timestamp_millis() + self.offset
+    }
+
+    /** Forget the keys that expired */
+    fn expire(&mut self) {
+        let now = self.now();
+        self.entries
+            .retain(|_, entry| entry.expiry.is_none_or(|expiry| expiry > now));
+    }
+
+    fn entry(&mut self, key: &[u8], default: Data) -> &mut Entry {
+        self.entries.entry(key.to_vec()).or_insert(Entry {
+            data: default,
+            expiry: None,
+        })
+    }
+
+    fn string(&self, key: &[u8]) -> RedisResult<Option<&Vec<u8>>> {
+        match self.entries.get(key).map(|entry| &entry.data) {
+            None => Ok(None),
+            Some(Data::String(value)) => Ok(Some(value)),
+            _ => Err(wrong_type()),
+        }
+    }
+
+    fn hash(&mut self, key: &[u8]) -> RedisResult<&mut BTreeMap<Vec<u8>, Vec<u8>>> {
+        match &mut self.entry(key, Data::Hash(BTreeMap::new())).data {
+            Data::Hash(hash) => Ok(hash),
+            _ => Err(wrong_type()),
+        }
+    }
+
+    fn set(&mut self, key: &[u8]) -> RedisResult<&mut BTreeSet<Vec<u8>>> {
+        match &mut self.entry(key, Data::Set(BTreeSet::new())).data {
+            Data::Set(set) => Ok(set),
+            _ => Err(wrong_type()),
+        }
+    }
+
+    fn sorted(&mut self, key: &[u8]) -> RedisResult<&mut BTreeMap<Vec<u8>, f64>> {
+        match &mut self.entry(key, Data::Sorted(BTreeMap::new())).data {
+            Data::Sorted(sorted) => Ok(sorted),
+            _ => Err(wrong_type()),
+        }
+    }
+
+    /** Members of the sorted set at `key`, ordered by score then member */
+    fn ordered(&mut self, key: &[u8]) -> RedisResult<Vec<(Vec<u8>, f64)>> {
+        let mut members: Vec<_> = self
+            .sorted(key)?
+            .iter()
+            .map(|(member, score)| (member.clone(), *score))
+            .collect();
+        members.sort_by(|(a, x), (b, y)| x.total_cmp(y).then_with(|| a.cmp(b)));
+
+        Ok(members)
+    }
+
+    /** Remove the collections left empty, like Redis does */
+    fn prune(&mut self, key: &[u8]) {
+        let empty = match self.entries.get(key).map(|entry| &entry.data) {
+            Some(Data::Hash(hash)) => hash.is_empty(),
+            Some(Data::Set(set)) => set.is_empty(),
+            Some(Data::Sorted(sorted)) => sorted.is_empty(),
+            _ => false,
+        };
+
+        if empty {
+            self.entries.remove(key);
+        }
+    }
+
+    fn incr(&mut self, key: &[u8], by: i64) -> RedisResult<Value> {
+        let value = self.string(key)?.map(|value| int(value)).transpose()?;
+        let value = value.unwrap_or_default() + by;
+
+        self.entry(key, Data::String(Vec::new())).data = Data::String(value.to_string().into());
+        Ok(Value::Int(value))
+    }
+
+    /** Set the expiry of `key` to the UNIX timestamp `at`, in milliseconds */
+    fn expire_at(&mut self, key: &[u8], at: i64) -> Value {
+        match self.entries.get_mut(key) {
+            Some(entry) => {
+                entry.expiry = Some(at);
+                Value::Int(1)
+            }
+            None => Value::Int(0),
+        }
+    }
+
+    /** Run the command made of `args`, starting with it's name */
+    fn exec(&mut self, args: &[Vec<u8>]) -> RedisResult<Value> {
+        self.expire();
+
+        let name = text(&args[0]).to_uppercase();
+        let args = &args[1..];
+        let key = args.first().map(Vec::as_slice).unwrap_or_default();
+
+        Ok(match name.as_str() {
+            "PING" => Value::Status(String::from("PONG")),
+            "PUBLISH" => Value::Int(0),
+            "CONFIG" => Value::Okay,
+
+            "GET" => self
+                .string(key)?
+                .map_or(Value::Nil, |value| Value::Data(value.clone())),
+            "SET" => {
+                let mut expiry = None;
+                let mut condition = None;
+                let mut options = args[2..].iter();
+                while let Some(option) = options.next() {
+                    match text(option).to_uppercase().as_str() {
+                        "NX" | "XX" => condition = Some(text(option).to_uppercase()),
+                        "EX" => expiry = Some(self.now() + 1000 * int(options.next().unwrap())?),
+                        "PX" => expiry = Some(self.now() + int(options.next().unwrap())?),
+                        "KEEPTTL" => expiry = self.entries.get(key).and_then(|entry| entry.expiry),
+                        option => panic!("The `SET` option `{}` is not supported", option),
+                    }
+                }
+
+                let exists = self.entries.contains_key(key);
+                match condition.as_deref() {
+                    Some("NX") if exists => return Ok(Value::Nil),
+                    Some("XX") if !exists => return Ok(Value::Nil),
+                    _ => (),
+                }
+
+                self.entries.insert(
+                    key.to_vec(),
+                    Entry {
+                        data: Data::String(args[1].clone()),
+                        expiry,
+                    },
+                );
+                Value::Okay
+            }
+            "SETEX" | "PSETEX" => {
+                let unit = if name == "SETEX" { "EX" } else { "PX" };
+                let args = [
+                    b"SET".to_vec(),
+                    key.to_vec(),
+                    args[2].clone(),
+                    unit.into(),
+                    args[1].clone(),
+                ];
+                return self.exec(&args);
+            }
+            "SETNX" => {
+                let args = [
+                    b"SET".to_vec(),
+                    key.to_vec(),
+                    args[1].clone(),
+                    b"NX".to_vec(),
+                ];
+                return Ok(Value::Int((self.exec(&args)? == Value::Okay) as i64));
+            }
+            "INCR" => self.incr(key, 1)?,
+            "DECR" => self.incr(key, -1)?,
+            "INCRBY" => self.incr(key, int(&args[1])?)?,
+            "DECRBY" => self.incr(key, -int(&args[1])?)?,
+
+            "DEL" => Value::Int(
+                args.iter()
+                    .filter(|key| self.entries.remove(*key).is_some())
+                    .count() as i64,
+            ),
+            "EXISTS" => Value::Int(
+                args.iter()
+                    .filter(|key| self.entries.contains_key(*key))
+                    .count() as i64,
+            ),
+            "EXPIRE" => {
+                let at = self.now() + 1000 * int(&args[1])?;
+                self.expire_at(key, at)
+            }
+            "PEXPIRE" => {
+                let at = self.now() + int(&args[1])?;
+                self.expire_at(key, at)
+            }
+            "EXPIREAT" => self.expire_at(key, 1000 * int(&args[1])?),
+            "PERSIST" => match self.entries.get_mut(key) {
+                Some(entry) if entry.expiry.is_some() => {
+                    entry.expiry = None;
+                    Value::Int(1)
+                }
+                _ => Value::Int(0),
+            },
+            "TTL" | "PTTL" => {
+                let now = self.now();
+                let unit = if name == "TTL" { 1000 } else { 1 };
+                Value::Int(match self.entries.get(key) {
+                    None => -2,
+                    Some(Entry { expiry: None, .. }) => -1,
+                    Some(Entry {
+                        expiry: Some(expiry),
+                        ..
+                    }) => (expiry - now + unit - 1) / unit,
+                })
+            }
+            "SCAN" => {
+                let mut pattern = &b"*"[..];
+                let mut options = args[1..].iter();
+                while let Some(option) = options.next() {
+                    match text(option).to_uppercase().as_str() {
+                        "MATCH" => pattern = options.next().unwrap(),
+                        "COUNT" => drop(options.next()),
+                        option => panic!("The `SCAN` option `{}` is not supported", option),
+                    }
+                }
+
+                let mut keys: Vec<_> = self
+                    .entries
+                    .keys()
+                    .filter(|key| matches(pattern, key))
+                    .cloned()
+                    .map(Value::Data)
+                    .collect();
+                keys.sort_by(|a, b| format!("{:?}", a).cmp(&format!("{:?}", b)));
+
+                Value::Bulk(vec![Value::Data(b"0".to_vec()), Value::Bulk(keys)])
+            }
+
+            "HSET" => {
+                let hash = self.hash(key)?;
+                let added = args[1..]
+                    .chunks(2)
+                    .filter(|pair| hash.insert(pair[0].clone(), pair[1].clone()).is_none())
+                    .count();
+                Value::Int(added as i64)
+            }
+            "HGET" => self
+                .hash(key)?
+                .get(&args[1])
+                .cloned()
+                .map_or(Value::Nil, Value::Data),
+            "HDEL" => {
+                let hash = self.hash(key)?;
+                let removed = args[1..]
+                    .iter()
+                    .filter(|field| hash.remove(*field).is_some())
+                    .count();
+                Value::Int(removed as i64)
+            }
+            "HINCRBY" => {
+                let by = int(&args[2])?;
+                let hash = self.hash(key)?;
+                let value = hash.get(&args[1]).map(|value| int(value)).transpose()?;
+                let value = value.unwrap_or_default() + by;
+                hash.insert(args[1].clone(), value.to_string().into());
+                Value::Int(value)
+            }
+            "HGETALL" => Value::Bulk(
+                self.hash(key)?
+                    .iter()
+                    .flat_map(|(field, value)| vec![field.clone(), value.clone()])
+                    .map(Value::Data)
+                    .collect(),
+            ),
+            "HKEYS" => Value::Bulk(self.hash(key)?.keys().cloned().map(Value::Data).collect()),
+            "HLEN" => Value::Int(self.hash(key)?.len() as i64),
+            "HEXISTS" => Value::Int(self.hash(key)?.contains_key(&args[1]) as i64),
+
+            "SADD" | "PFADD" => {
+                let set = self.set(key)?;
+                let added = args[1..]
+                    .iter()
+                    .filter(|member| set.insert(member.to_vec()))
+                    .count();
+                Value::Int(match name.as_str() {
+                    "PFADD" => (added > 0) as i64,
+                    _ => added as i64,
+                })
+            }
+            "SREM" => {
+                let set = self.set(key)?;
+                let removed = args[1..]
+                    .iter()
+                    .filter(|member| set.remove(*member))
+                    .count();
+                Value::Int(removed as i64)
+            }
+            "SISMEMBER" => Value::Int(self.set(key)?.contains(&args[1]) as i64),
+            "SMEMBERS" => Value::Bulk(self.set(key)?.iter().cloned().map(Value::Data).collect()),
+            "SCARD" | "PFCOUNT" => Value::Int(self.set(key)?.len() as i64),
+
+            "ZADD" => {
+                let sorted = self.sorted(key)?;
+                let mut added = 0;
+                for pair in args[1..].chunks(2) {
+                    added += sorted.insert(pair[1].clone(), float(&pair[0])?).is_none() as i64;
+                }
+                Value::Int(added)
+            }
+            "ZREM" => {
+                let sorted = self.sorted(key)?;
+                let removed = args[1..]
+                    .iter()
+                    .filter(|member| sorted.remove(*member).is_some())
+                    .count();
+                Value::Int(removed as i64)
+            }
+            "ZSCORE" => self
+                .sorted(key)?
+                .get(&args[1])
+                .map_or(Value::Nil, |value| Value::Data(score(*value))),
+            "ZCARD" => Value::Int(self.sorted(key)?.len() as i64),
+            "ZSCAN" => {
+                let mut pattern = &b"*"[..];
+                let mut options = args[2..].iter();
+                while let Some(option) = options.next() {
+                    match text(option).to_uppercase().as_str() {
+                        "MATCH" => pattern = options.next().unwrap(),
+                        "COUNT" => drop(options.next()),
+                        option => panic!("The `ZSCAN` option `{}` is not supported", option),
+                    }
+                }
+
+                let members = self
+                    .ordered(key)?
+                    .into_iter()
+                    .filter(|(member, _)| matches(pattern, member))
+                    .flat_map(|(member, value)| [Value::Data(member), Value::Data(score(value))])
+                    .collect();
+
+                Value::Bulk(vec![Value::Data(b"0".to_vec()), Value::Bulk(members)])
+            }
+            "ZRANGE" | "ZRANGEBYSCORE" => {
+                let members = self.ordered(key)?;
+                let mut scores = false;
+                let mut limit = None;
+                let mut options = args[3..].iter();
+                while let Some(option) = options.next() {
+                    match text(option).to_uppercase().as_str() {
+                        "WITHSCORES" => scores = true,
+                        "LIMIT" => {
+                            limit = Some((
+                                int(options.next().unwrap())? as usize,
+                                int(options.next().unwrap())?,
+                            ))
+                        }
+                        option => panic!("The `{}` option `{}` is not supported", name, option),
+                    }
+                }
+
+                let members: Vec<_> = match name.as_str() {
+                    "ZRANGE" => {
+                        members[range(int(&args[1])?, int(&args[2])?, members.len())].to_vec()
+                    }
+                    _ => {
+                        let (min, max) = (bound(&args[1])?, bound(&args[2])?);
+                        let members = members
+                            .into_iter()
+                            .filter(|(_, score)| within(*score, min, max));
+
+                        match limit {
+                            Some((offset, count)) if count >= 0 => {
+                                members.skip(offset).take(count as usize).collect()
+                            }
+                            Some((offset, _)) => members.skip(offset).collect(),
+                            None => members.collect(),
+                        }
+                    }
+                };
+
+                Value::Bulk(
+                    members
+                        .into_iter()
+                        .flat_map(|(member, value)| match scores {
+                            true => vec![member, score(value)],
+                            false => vec![member],
+                        })
+                        .map(Value::Data)
+                        .collect(),
+                )
+            }
+            "ZREMRANGEBYSCORE" => {
+                let (min, max) = (bound(&args[1])?, bound(&args[2])?);
+                let sorted = self.sorted(key)?;
+                let before = sorted.len();
+                sorted.retain(|_, score| !within(*score, min, max));
+                Value::Int((before - sorted.len()) as i64)
+            }
+
+            "SCRIPT" => match text(key).to_uppercase().as_str() {
+                "LOAD" => {
+                    let digest = sha1_smol::Sha1::from(&args[1]).digest().to_string();
+                    self.scripts.insert(digest.clone(), args[1].clone());
+                    Value::Data(digest.into_bytes())
+                }
+                command => panic!("The `SCRIPT {}` command is not supported", command),
+            },
+            "EVAL" => return self.eval(&args[0], &args[1..]),
+            "EVALSHA" => match self.scripts.get(&text(key).to_lowercase()) {
+                Some(script) => return self.eval(&script.clone(), &args[1..]),
+                None => {
+                    return Err(error(
+                        ErrorKind::NoScriptError,
+                        "NOSCRIPT No matching script",
+                    ))
+                }
+            },
+
+            command => panic!("The `{}` command is not supported", command),
+        })
+        .inspect(|_| {
+            if !key.is_empty() {
+                self.prune(key);
+            }
+        })
+    }
+
+    /** Run the Lua `script`, with the `args` made of the number of keys, the keys and the arguments */
+    fn eval(&mut self, script: &[u8], args: &[Vec<u8>]) -> RedisResult<Value> {
+        let count = int(&args[0])? as usize;
+        let (keys, argv) = args[1..].split_at(count);
+
+        let lua = mlua::Lua::new();
+        lua.scope(|scope| {
+            let redis = lua.create_table()?;
+            redis.set(
+                "call",
+                scope.create_function_mut(|lua, args: mlua::Variadic<mlua::Value>| {
+                    let args = args
+                        .iter()
+                        .map(|arg| match arg {
+                            mlua::Value::String(arg) => Ok(arg.as_bytes().to_vec()),
+                            mlua::Value::Integer(arg) => Ok(arg.to_string().into_bytes()),
+                            mlua::Value::Number(arg) if arg.fract() == 0.0 => {
+                                Ok((*arg as i64).to_string().into_bytes())
+                            }
+                            mlua::Value::Number(arg) => Ok(arg.to_string().into_bytes()),
+                            arg => Err(mlua::Error::RuntimeError(format!(
+                                "Lua redis() command arguments must be strings or integers, not {}",
+                                arg.type_name()
+                            ))),
+                        })
+                        .collect::<mlua::Result<Vec<_>>>()?;
+
+                    let value = self
+                        .exec(&args)
+                        .map_err(|err| mlua::Error::RuntimeError(err.to_string()))?;
+                    to_lua(lua, value)
+                })?,
+            )?;
+
+            let globals = lua.globals();
+            globals.set("redis", redis)?;
+            globals.set("KEYS", strings(&lua, keys)?)?;
+            globals.set("ARGV", strings(&lua, argv)?)?;
+
+            from_lua(lua.load(script).eval()?)
+        })
+        .map_err(|err| match err {
+            mlua::Error::ExternalError(err) => match err.downcast_ref::<RedisError>() {
+                Some(err) => RedisError::from((err.kind(), "The script failed", err.to_string())),
+                None => RedisError::from((
+                    ErrorKind::ResponseError,
+                    "The script failed",
+                    err.to_string(),
+                )),
+            },
+            err => RedisError::from((
+                ErrorKind::ResponseError,
+                "The script failed",
+                err.to_string(),
+            )),
+        })
+    }
+}
+
+fn strings<'lua>(lua: &'lua mlua::Lua, values: &[Vec<u8>]) -> mlua::Result<mlua::Table<'lua>> {
+    lua.create_sequence_from(
+        values
+            .iter()
+            .map(|value| lua.create_string(value))
+            .collect::<mlua::Result<Vec<_>>>()?,
+    )
+}
+
+/** Convert a reply to the Lua value a script sees, like Redis does */
+fn to_lua(lua: &mlua::Lua, value: Value) -> mlua::Result<mlua::Value<'_>> {
+    Ok(match value {
+        Value::Nil => mlua::Value::Boolean(false),
+        Value::Int(value) => mlua::Value::Number(value as f64),
+        Value::Data(value) => mlua::Value::String(lua.create_string(&value)?),
+        Value::Bulk(values) => mlua::Value::Table(
+            lua.create_sequence_from(
+                values
+                    .into_iter()
+                    .map(|value| to_lua(lua, value))
+                    .collect::<mlua::Result<Vec<_>>>()?,
+            )?,
+        ),
+        Value::Okay => mlua::Value::Table(lua.create_table_from([("ok", "OK")])?),
+        Value::Status(status) => mlua::Value::Table(lua.create_table_from([("ok", status)])?),
+    })
+}
+
+/** Convert the value returned by a script to it's reply, like Redis does */
+fn from_lua(value: mlua::Value<'_>) -> mlua::Result<Value> {
+    Ok(match value {
+        mlua::Value::Nil | mlua::Value::Boolean(false) => Value::Nil,
+        mlua::Value::Boolean(true) => Value::Int(1),
+        mlua::Value::Integer(value) => Value::Int(value),
+        mlua::Value::Number(value) => Value::Int(value as i64),
+        mlua::Value::String(value) => Value::Data(value.as_bytes().to_vec()),
+        mlua::Value::Table(table) => {
+            if let Some(err) = table.get::<_, Option<String>>("err")? {
+                return Err(mlua::Error::RuntimeError(err));
+            }
+            if let Some(status) = table.get::<_, Option<String>>("ok")? {
+                return Ok(Value::Status(status));
+            }
+
+            let mut values = Vec::new();
+            for value in table.sequence_values::<mlua::Value>() {
+                values.push(from_lua(value?)?);
+            }
+            Value::Bulk(values)
+        }
+        value => {
+            return Err(mlua::Error::RuntimeError(format!(
+                "Unsupported script reply of type {}",
+                value.type_name()
+            )))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use redis::AsyncCommands;
+
+    use super::*;
+    use crate::types::Pool;
+
+    #[rocket::async_test]
+    async fn scripts_are_loaded_and_run() {
+        let (pool, _) = Pool::memory();
+        let mut conn = pool.get();
+
+        let script = redis::Script::new(
+            r"
+            redis.call('SET', KEYS[1], ARGV[1])
+            return {redis.call('INCRBY', KEYS[1], 2), redis.call('GET', 'missing')}
+            ",
+        );
+        let (value, missing): (i64, Option<String>) = script
+            .key("counter")
+            .arg(40)
+            .invoke_async(&mut conn)
+            .await
+            .unwrap();
+
+        assert_eq!(value, 42);
+        assert_eq!(missing, None);
+        assert_eq!(conn.get::<_, i64>("counter").await.unwrap(), 42);
+    }
+
+    #[rocket::async_test]
+    async fn transactions_are_answered_at_once() {
+        let (pool, _) = Pool::memory();
+        let mut conn = pool.get();
+
+        let (added, value): (u8, String) = redis::pipe()
+            .atomic()
+            .hset("hash", "field", "value")
+            .hget("hash", "field")
+            .query_async(&mut conn)
+            .await
+            .unwrap();
+
+        assert_eq!((added, value.as_str()), (1, "value"));
+    }
+
+    #[rocket::async_test]
+    async fn keys_expire_with_the_clock() {
+        let (pool, memory) = Pool::memory();
+        let mut conn = pool.get();
+
+        conn.set_ex::<_, _, ()>("key", "value", 10).await.unwrap();
+        memory.advance(Duration::from_secs(9));
+        assert!(conn.exists::<_, bool>("key").await.unwrap());
+
+        memory.advance(Duration::from_secs(1));
+        assert!(!conn.exists::<_, bool>("key").await.unwrap());
+    }
+}
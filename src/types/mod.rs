@@ -1,14 +1,95 @@
+mod agent;
+mod analytics;
+mod blocklist;
 mod error;
 mod host;
+mod identity;
+#[cfg(test)]
+mod memory;
+mod pool;
+mod quota;
 mod record;
 mod retention;
+mod signature;
+mod slug;
+mod target;
+mod throttle;
+mod tombstone;
+mod topology;
+mod upload;
+mod webhook;
 
 /** The storage prefix for keys on Redis */
 pub const STORAGE_PREFIX: &str = "shrekd:";
 
+/** The storage prefix for resumable uploads on Redis, also used for their partial files */
+pub const UPLOAD_PREFIX: &str = "shrekd.upload:";
+
+/** The storage prefix for the locks serializing the appends to resumable uploads on Redis */
+pub const UPLOAD_LOCK_PREFIX: &str = "shrekd.upload-lock:";
+
+/** The storage prefix for the API keys on Redis, by hash, stored as JSON to be provisioned by hand */
+pub const KEY_PREFIX: &str = "shrekd.key:";
+
+/** The storage prefix for the content blocklists on Redis */
+pub const BLOCKLIST_PREFIX: &str = "shrekd.blocklist:";
+
+/** The storage prefix for the claims on the custom slugs of records being created on Redis */
+pub const CLAIM_PREFIX: &str = "shrekd.claim:";
+
+/** The storage prefix for the tombstones of dead records on Redis */
+pub const TOMBSTONE_PREFIX: &str = "shrekd.tombstone:";
+
+/** The storage prefix for the sets of records by owner on Redis */
+pub const OWNER_PREFIX: &str = "shrekd.owner:";
+
+/** The key of the hash of owners by record slug on Redis */
+pub const OWNERS_KEY: &str = "shrekd.owners";
+
+/** The storage prefix for the rolling quotas of the clients on Redis */
+pub const THROTTLE_PREFIX: &str = "shrekd.throttle:";
+
+/** The storage prefix for the click analytics of the url redirects on Redis */
+pub const ANALYTICS_PREFIX: &str = "shrekd.analytics:";
+
+/** The storage prefix for the unique visitors of the url redirects on Redis, as HyperLogLogs */
+pub const VISITORS_PREFIX: &str = "shrekd.visitors:";
+
+/** The key holding the storage usage, in bytes, on Redis */
+pub const USAGE_KEY: &str = "shrekd.usage";
+
+/** The key of the hash of the sizes reserved in the quota, by record slug or upload key, on Redis */
+pub const RESERVED_KEY: &str = "shrekd.reserved";
+
+/** The key of the sorted set of `file` records by expiry on Redis */
+pub const EXPIRIES_KEY: &str = "shrekd.expiries";
+
+/** The key of the hash of records subscribed to webhooks on Redis */
+pub const WEBHOOKS_KEY: &str = "shrekd.webhooks";
+
+/** The key of the sorted set of pending webhook deliveries by due date on Redis */
+pub const DELIVERIES_KEY: &str = "shrekd.deliveries";
+
 pub use {
+    agent::UserAgent,
+    analytics::{Analytics, Visit},
+    blocklist::Blocklist,
     error::{Error, Result},
-    host::HostBase,
-    record::{Record, RecordData, RecordSettings},
-    retention::RetentionCurve,
+    host::{ClientIp, HostBase},
+    identity::{ApiKey, Identity, KeyPolicy},
+    pool::{Pool, PooledConnection},
+    quota::{Quota, QuotaPolicy},
+    record::{Record, RecordData, RecordSettings, RecordType, RedirectStatus},
+    retention::{RetentionCurve, Shape},
+    signature::Signer,
+    slug::{SlugGrammar, SlugStyle},
+    target::TargetPolicy,
+    throttle::{Charge, RateLimit, Throttle},
+    tombstone::{Reason, Tombstone},
+    topology::{RedisMode, Topology},
+    upload::Upload,
+    webhook::{Delivery, Webhooks},
 };
+
+#[cfg(test)]
+pub use memory::Memory;
@@ -0,0 +1,269 @@
+use redis::{
+    aio::{ConnectionLike, ConnectionManager},
+    cluster_async::ClusterConnection,
+    Cmd, ErrorKind, Pipeline, RedisError, RedisFuture, Value,
+};
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+    time::Duration,
+};
+
+use super::{Result, Topology};
+
+/** Initial delay between the attempts to open the [`Pool`], doubled upon every failure, in milliseconds */
+const CONNECT_BACKOFF: u64 = 500;
+/** Maximum delay between the attempts to open the [`Pool`], in milliseconds */
+const CONNECT_BACKOFF_MAX: u64 = 30_000;
+
+/** Wrap the `future` in a timeout, turning it into a [`RedisError`] when elapsed */
+async fn timeout<T>(
+    duration: Duration,
+    future: impl Future<Output = redis::RedisResult<T>>,
+) -> redis::RedisResult<T> {
+    tokio::time::timeout(duration, future)
+        .await
+        .map_err(|_| RedisError::from((ErrorKind::IoError, "The Redis server timed out")))?
+}
+
+/** A multiplexed connection to the Redis deployment, depending on it's [`Topology`] */
+#[derive(Clone)]
+enum Backend {
+    Standalone(ConnectionManager),
+    Sentinel(Failover),
+    Cluster(ClusterConnection),
+    #[cfg(test)]
+    Memory(super::memory::Memory),
+}
+
+/** A multiplexed connection to the Sentinel-elected master, following it across failovers */
+#[derive(Clone)]
+struct Failover {
+    topology: Topology,
+    /** The connection to the current master, along with the number of times it was discovered */
+    current: Arc<RwLock<(usize, ConnectionManager)>>,
+    /** Held while discovering the new master, so it's only done once per failover */
+    discovery: Arc<tokio::sync::Mutex<()>>,
+    timeout: Duration,
+}
+
+impl Failover {
+    /** Connect to the current master of the `topology` */
+    async fn new(topology: &Topology, timeout: Duration) -> redis::RedisResult<Self> {
+        let conn = Self::connect(topology, timeout).await?;
+
+        Ok(Self {
+            topology: topology.clone(),
+            current: Arc::new(RwLock::new((0, conn))),
+            discovery: Arc::new(tokio::sync::Mutex::new(())),
+            timeout,
+        })
+    }
+
+    /** Discover the master of the `topology`, and connect to it */
+    async fn connect(
+        topology: &Topology,
+        timeout: Duration,
+    ) -> redis::RedisResult<ConnectionManager> {
+        self::timeout(timeout, async {
+            ConnectionManager::new(topology.master().await?).await
+        })
+        .await
+    }
+
+    #[inline]
+    fn current(&self) -> (usize, ConnectionManager) {
+        self.current.read().unwrap().clone() // <- unwrap here is safe, since the lock is never held across a panic
+    }
+
+    /** Whether the `err` hints at a failover, the master being either unreachable or demoted to a replica */
+    fn is_failover(err: &RedisError) -> bool {
+        err.kind() == ErrorKind::ReadOnly
+            || err.is_io_error()
+            || err.is_connection_dropped()
+            || err.is_connection_refusal()
+    }
+
+    /** Discover the new master, unless it was already done since the `generation` of the failed connection */
+    async fn rediscover(&self, generation: usize) -> redis::RedisResult<ConnectionManager> {
+        let _discovery = self.discovery.lock().await;
+
+        let (current, conn) = self.current();
+        if current != generation {
+            return Ok(conn);
+        }
+
+        let conn = Self::connect(&self.topology, self.timeout).await?;
+        *self.current.write().unwrap() = (generation + 1, conn.clone());
+
+        Ok(conn)
+    }
+
+    /** Run the `command` against the current master, discovering it again upon a failover */
+    async fn run<T, F, Fut>(self, command: F) -> redis::RedisResult<T>
+    where
+        F: Fn(ConnectionManager) -> Fut,
+        Fut: Future<Output = redis::RedisResult<T>>,
+    {
+        let (generation, conn) = self.current();
+
+        match timeout(self.timeout, command(conn)).await {
+            Err(err) if Self::is_failover(&err) => {
+                tracing::warn!("Lost the Redis master, discovering it again: {}", err);
+
+                let conn = self.rediscover(generation).await?;
+
+                /* Only the commands refused by a demoted master are sure not to have been applied */
+                if err.kind() == ErrorKind::ReadOnly {
+                    timeout(self.timeout, command(conn)).await
+                } else {
+                    Err(err)
+                }
+            }
+            result => result,
+        }
+    }
+}
+
+/** A pool of multiplexed connections to the Redis server, reconnecting automatically */
+pub struct Pool {
+    connections: Vec<Backend>,
+    next: AtomicUsize,
+    timeout: Duration,
+}
+
+impl Pool {
+    /** Open `size` multiplexed connections to the `topology`, with the provided command `timeout` */
+    pub async fn new(topology: &Topology, size: usize, timeout: Duration) -> Result<Self> {
+        let connections = futures::future::try_join_all((0..size.max(1)).map(|_| async move {
+            Ok::<_, RedisError>(match topology {
+                Topology::Standalone(client) => Backend::Standalone(
+                    self::timeout(timeout, ConnectionManager::new(client.clone())).await?,
+                ),
+                Topology::Sentinel(..) => {
+                    Backend::Sentinel(Failover::new(topology, timeout).await?)
+                }
+                Topology::Cluster(client, _) => {
+                    Backend::Cluster(self::timeout(timeout, client.get_async_connection()).await?)
+                }
+            })
+        }))
+        .await?;
+
+        Ok(Self {
+            connections,
+            next: AtomicUsize::new(0),
+            timeout,
+        })
+    }
+
+    /** Open the [`Pool`] like [`Pool::new`], retrying with an exponential backoff until the Redis deployment is
+     * reachable, so that an outage at boot only delays the startup */
+    pub async fn connect(topology: &Topology, size: usize, timeout: Duration) -> Self {
+        let mut backoff = CONNECT_BACKOFF;
+
+        loop {
+            match Self::new(topology, size, timeout).await {
+                Ok(pool) => return pool,
+                Err(err) => {
+                    tracing::warn!(
+                        "Failed to connect to the Redis server, retrying in {}ms: {}",
+                        backoff,
+                        err
+                    );
+
+                    tokio::time::sleep(Duration::from_millis(backoff)).await;
+                    backoff = (backoff * 2).min(CONNECT_BACKOFF_MAX);
+                }
+            }
+        }
+    }
+
+    /** Create a [`Pool`] over an empty in-memory database, for the tests */
+    #[cfg(test)]
+    pub fn memory() -> (Self, super::memory::Memory) {
+        let memory = super::memory::Memory::default();
+        let pool = Self {
+            connections: vec![Backend::Memory(memory.clone())],
+            next: AtomicUsize::new(0),
+            timeout: Duration::from_secs(1),
+        };
+
+        (pool, memory)
+    }
+
+    /** Get a connection from the [`Pool`], in a round-robin fashion */
+    pub fn get(&self) -> PooledConnection {
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.connections.len();
+
+        PooledConnection {
+            inner: self.connections[index].clone(),
+            timeout: self.timeout,
+        }
+    }
+}
+
+/** A connection borrowed from the [`Pool`], enforcing a timeout on every command */
+#[derive(Clone)]
+pub struct PooledConnection {
+    inner: Backend,
+    timeout: Duration,
+}
+
+impl ConnectionLike for PooledConnection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        match &mut self.inner {
+            Backend::Standalone(conn) => {
+                Box::pin(timeout(self.timeout, conn.req_packed_command(cmd)))
+            }
+            Backend::Sentinel(failover) => Box::pin(
+                failover
+                    .clone()
+                    .run(move |mut conn| async move { conn.req_packed_command(cmd).await }),
+            ),
+            Backend::Cluster(conn) => Box::pin(timeout(self.timeout, conn.req_packed_command(cmd))),
+            #[cfg(test)]
+            Backend::Memory(memory) => Box::pin(async move { memory.query(cmd) }),
+        }
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        match &mut self.inner {
+            Backend::Standalone(conn) => Box::pin(timeout(
+                self.timeout,
+                conn.req_packed_commands(cmd, offset, count),
+            )),
+            Backend::Sentinel(failover) => {
+                Box::pin(failover.clone().run(move |mut conn| async move {
+                    conn.req_packed_commands(cmd, offset, count).await
+                }))
+            }
+            Backend::Cluster(conn) => Box::pin(timeout(
+                self.timeout,
+                conn.req_packed_commands(cmd, offset, count),
+            )),
+            #[cfg(test)]
+            Backend::Memory(memory) => {
+                Box::pin(async move { memory.query_pipeline(cmd, offset, count) })
+            }
+        }
+    }
+
+    fn get_db(&self) -> i64 {
+        match &self.inner {
+            Backend::Standalone(conn) => conn.get_db(),
+            Backend::Sentinel(failover) => failover.current().1.get_db(),
+            Backend::Cluster(conn) => conn.get_db(),
+            #[cfg(test)]
+            Backend::Memory(_) => 0,
+        }
+    }
+}
@@ -0,0 +1,363 @@
+use redis::AsyncCommands;
+use rocket::data::ByteUnit;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tokio::{fs, io};
+
+use crate::Error;
+
+use super::{
+    PooledConnection, Reason, Record, RecordData, Result, Tombstone, Webhooks, EXPIRIES_KEY,
+    RESERVED_KEY, UPLOAD_PREFIX, USAGE_KEY,
+};
+
+/** Add `ARGV[1]` bytes to the usage, starting over from zero if it ever went negative */
+const RESERVE_SCRIPT: &str = r"
+local usage = math.max(tonumber(redis.call('GET', KEYS[1]) or '0'), 0) + tonumber(ARGV[1])
+redis.call('SET', KEYS[1], usage)
+return usage
+";
+
+/** Remove `ARGV[1]` bytes from the usage, without ever going below zero */
+const UNRESERVE_SCRIPT: &str = r"
+local usage = redis.call('DECRBY', KEYS[1], ARGV[1])
+if usage < 0 then
+    redis.call('SET', KEYS[1], 0)
+    usage = 0
+end
+return usage
+";
+
+/** The behavior to adopt when an upload would exceed the storage quota */
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum QuotaPolicy {
+    /** Reject the upload with a `507 Insufficient Storage` */
+    Reject,
+    /** Evict `file` records by order of soonest expiry until the upload fits */
+    Evict,
+}
+
+/** Represents the global storage quota, with the usage being tracked in Redis */
+#[derive(Debug)]
+pub struct Quota {
+    data_dir: PathBuf,
+    limit: Option<u64>,
+    policy: QuotaPolicy,
+    tombstones: u64,
+    webhooks: Webhooks,
+}
+
+impl Quota {
+    /** Create a new [`Quota`] from it's parameters */
+    #[inline]
+    pub const fn new(
+        data_dir: PathBuf,
+        limit: Option<u64>,
+        policy: QuotaPolicy,
+        tombstones: u64,
+        webhooks: Webhooks,
+    ) -> Self {
+        Self {
+            data_dir,
+            limit,
+            policy,
+            tombstones,
+            webhooks,
+        }
+    }
+
+    /** Get the maximum storage usage, in bytes, if any */
+    #[inline]
+    pub const fn limit(&self) -> Option<u64> {
+        self.limit
+    }
+
+    /** Get the current storage usage from the Redis server, in bytes */
+    #[inline]
+    pub async fn usage(conn: &mut PooledConnection) -> Result<u64> {
+        Ok(conn
+            .get::<_, Option<i64>>(USAGE_KEY)
+            .await?
+            .unwrap_or_default()
+            .max(0) as u64)
+    }
+
+    /** Add `size` bytes to the usage, returning the new usage */
+    async fn add(size: u64, conn: &mut PooledConnection) -> Result<i64> {
+        Ok(redis::Script::new(RESERVE_SCRIPT)
+            .key(USAGE_KEY)
+            .arg(size)
+            .invoke_async(conn)
+            .await?)
+    }
+
+    /** Reserve `size` bytes in the quota, evicting records if the policy allows it */
+    pub async fn reserve(&self, size: u64, conn: &mut PooledConnection) -> Result<()> {
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None => return Self::add(size, conn).await.map(drop),
+        };
+
+        if size > limit {
+            return Err(Error::InsufficientStorage(format!(
+                "the upload of {} is bigger than the whole storage quota",
+                ByteUnit::from(size)
+            )));
+        }
+
+        loop {
+            /* Optimistically account for the upload, and roll back if it doesn't fit */
+            let usage = Self::add(size, conn).await?;
+            if usage <= limit as i64 {
+                return Ok(());
+            }
+            self.unreserve(size, conn).await?;
+
+            let candidate = match self.policy {
+                QuotaPolicy::Reject => None,
+                QuotaPolicy::Evict => conn
+                    .zrange::<_, Vec<String>>(EXPIRIES_KEY, 0, 0)
+                    .await?
+                    .pop(),
+            };
+
+            match candidate {
+                Some(slug) => self.evict(&slug, conn).await?,
+                None => {
+                    return Err(Error::InsufficientStorage(format!(
+                        "the storage quota of {} has been reached",
+                        ByteUnit::from(limit)
+                    )))
+                }
+            }
+        }
+    }
+
+    /** Give back `size` bytes to the quota, for an upload that did not go through */
+    #[inline]
+    pub async fn unreserve(&self, size: u64, conn: &mut PooledConnection) -> Result<()> {
+        redis::Script::new(UNRESERVE_SCRIPT)
+            .key(USAGE_KEY)
+            .arg(size)
+            .invoke_async::<_, i64>(conn)
+            .await?;
+
+        Ok(())
+    }
+
+    /** Mark the `size` bytes reserved by `name`, being a record's slug or an upload's key,
+     * so that only what was counted is ever released */
+    #[inline]
+    pub async fn mark(&self, name: &str, size: u64, conn: &mut PooledConnection) -> Result<()> {
+        Ok(conn.hset(RESERVED_KEY, name, size).await?)
+    }
+
+    /** Take over the reservation of `name`, returning it's size if it had one */
+    async fn claim(&self, name: &str, conn: &mut PooledConnection) -> Result<Option<u64>> {
+        let (size, _): (Option<u64>, u8) = redis::pipe()
+            .atomic()
+            .hget(RESERVED_KEY, name)
+            .hdel(RESERVED_KEY, name)
+            .query_async(conn)
+            .await?;
+
+        Ok(size)
+    }
+
+    /** Hand the reservation of the upload identified by `id` over to the record at `slug` */
+    pub async fn transfer(&self, id: &str, slug: &str, conn: &mut PooledConnection) -> Result<()> {
+        if let Some(size) = self.claim(&[UPLOAD_PREFIX, id].concat(), conn).await? {
+            self.mark(slug, size, conn).await?;
+        }
+
+        Ok(())
+    }
+
+    /** Give back the reservation of the upload identified by `id` once it's abandonned, returning whether it had one */
+    pub async fn release_upload(&self, id: &str, conn: &mut PooledConnection) -> Result<bool> {
+        match self.claim(&[UPLOAD_PREFIX, id].concat(), conn).await? {
+            Some(size) => {
+                tracing::debug!(
+                    "Released the {} reserved by the upload `{}`",
+                    ByteUnit::from(size),
+                    id
+                );
+
+                self.unreserve(size, conn).await.map(|_| true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /** List the identifiers of the uploads holding a reservation */
+    pub async fn uploads(conn: &mut PooledConnection) -> Result<Vec<String>> {
+        let names: Vec<String> = conn.hkeys(RESERVED_KEY).await?;
+
+        Ok(names
+            .into_iter()
+            .filter_map(|name| name.strip_prefix(UPLOAD_PREFIX).map(String::from))
+            .collect())
+    }
+
+    /** Remove the file associated with the `slug` if any, and free it's size from the usage if it was counted */
+    pub async fn release(&self, slug: &str, conn: &mut PooledConnection) -> Result<()> {
+        let path = self.data_dir.join(slug);
+
+        /* Claiming the reservation first ensures it's only given back once */
+        let reserved = self.claim(slug, &mut *conn).await?;
+        /* Files reserved before the reservations were marked are only known from the index */
+        let indexed: bool = conn.zrem(EXPIRIES_KEY, slug).await?;
+
+        let removed = match fs::metadata(&path).await {
+            /* File exists, we remove it */
+            Ok(metadata) => match fs::remove_file(&path).await {
+                Ok(()) => {
+                    tracing::debug!("Removed the {:?} since it's record is gone", path);
+
+                    Some(metadata.len())
+                }
+                Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+                Err(err) => return Err(err.into()),
+            },
+            /* Otherwise there is nothing to remove */
+            _ => {
+                tracing::debug!("File was not found, so we have nothing to remove");
+
+                None
+            }
+        };
+
+        match (reserved, removed) {
+            (Some(size), _) => self.unreserve(size, conn).await,
+            (None, Some(size)) if indexed => self.unreserve(size, conn).await,
+            /* Never counted, like the files written before the quota existed */
+            _ => Ok(()),
+        }
+    }
+
+    /** Forcefully remove the `slug`'s [`Record`] and it's file, to make room for others */
+    async fn evict(&self, slug: &str, conn: &mut PooledConnection) -> Result<()> {
+        if let Some(record) = Record::fetch(slug, &mut *conn).await? {
+            if let RecordData::File { .. } = record.data() {
+                tracing::info!("Evicting {:?} to satisfy the storage quota", record);
+
+                record.delete(&mut *conn).await?;
+                Tombstone::new(Reason::Evicted)
+                    .bury(slug, Some(self.tombstones), &mut *conn)
+                    .await?;
+                self.webhooks
+                    .deleted(slug, Reason::Evicted, &mut *conn)
+                    .await?;
+            }
+        }
+
+        self.release(slug, conn).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::*;
+    use crate::types::Pool;
+
+    fn quota(data_dir: PathBuf, limit: Option<u64>, policy: QuotaPolicy) -> Quota {
+        Quota::new(
+            data_dir,
+            limit,
+            policy,
+            60,
+            Webhooks::new(None, None, 0, false),
+        )
+    }
+
+    #[rocket::async_test]
+    async fn usage_never_goes_negative() {
+        let (pool, _) = Pool::memory();
+        let mut conn = pool.get();
+        let quota = quota(std::env::temp_dir(), None, QuotaPolicy::Reject);
+
+        quota.unreserve(10, &mut conn).await.unwrap();
+        assert_eq!(Quota::usage(&mut conn).await.unwrap(), 0);
+
+        /* A usage that drifted below zero starts over from zero */
+        conn.set::<_, _, ()>(USAGE_KEY, -30).await.unwrap();
+        quota.reserve(20, &mut conn).await.unwrap();
+        assert_eq!(Quota::usage(&mut conn).await.unwrap(), 20);
+    }
+
+    #[rocket::async_test]
+    async fn rejected_reservations_are_rolled_back() {
+        let (pool, _) = Pool::memory();
+        let mut conn = pool.get();
+        let quota = quota(std::env::temp_dir(), Some(100), QuotaPolicy::Reject);
+
+        quota.reserve(60, &mut conn).await.unwrap();
+        for size in [50, 101] {
+            match quota.reserve(size, &mut conn).await {
+                Err(Error::InsufficientStorage(_)) => (),
+                _ => panic!("The reservation of {} bytes went through", size),
+            }
+        }
+
+        assert_eq!(Quota::usage(&mut conn).await.unwrap(), 60);
+    }
+
+    #[rocket::async_test]
+    async fn evictions_start_from_the_soonest_expiry() {
+        let (pool, _) = Pool::memory();
+        let mut conn = pool.get();
+        let data_dir = std::env::temp_dir().join(format!("shrekd-{}", rand::random::<u64>()));
+        fs::create_dir_all(&data_dir).await.unwrap();
+        let quota = quota(data_dir.clone(), Some(130), QuotaPolicy::Evict);
+
+        for (slug, hours) in [("soon", 1), ("later", 2)] {
+            let path = data_dir.join(slug);
+            fs::write(&path, [0; 60]).await.unwrap();
+            quota.reserve(60, &mut conn).await.unwrap();
+            quota.mark(slug, 60, &mut conn).await.unwrap();
+
+            let expiry = Utc::now() + Duration::hours(hours);
+            Record::file(slug.into(), path, 60, slug.into(), None, Some(expiry))
+                .persist(&mut conn)
+                .await
+                .unwrap();
+        }
+
+        quota.reserve(60, &mut conn).await.unwrap();
+
+        assert!(Record::fetch("soon", &mut conn).await.unwrap().is_none());
+        assert!(Tombstone::exists("soon", &mut conn).await.unwrap());
+        assert!(fs::metadata(data_dir.join("soon")).await.is_err());
+        assert!(Record::fetch("later", &mut conn).await.unwrap().is_some());
+        assert_eq!(Quota::usage(&mut conn).await.unwrap(), 120);
+
+        fs::remove_dir_all(data_dir).await.unwrap();
+    }
+
+    #[rocket::async_test]
+    async fn reservations_are_released_once() {
+        let (pool, _) = Pool::memory();
+        let mut conn = pool.get();
+        let quota = quota(std::env::temp_dir(), None, QuotaPolicy::Reject);
+
+        quota.reserve(50, &mut conn).await.unwrap();
+        quota
+            .mark(&[UPLOAD_PREFIX, "upload"].concat(), 50, &mut conn)
+            .await
+            .unwrap();
+        assert_eq!(Quota::uploads(&mut conn).await.unwrap(), ["upload"]);
+
+        /* The reservation follows the upload into it's record */
+        quota.transfer("upload", "slug", &mut conn).await.unwrap();
+        assert!(!quota.release_upload("upload", &mut conn).await.unwrap());
+        assert_eq!(Quota::usage(&mut conn).await.unwrap(), 50);
+
+        for _ in 0..2 {
+            quota.release("slug", &mut conn).await.unwrap();
+            assert_eq!(Quota::usage(&mut conn).await.unwrap(), 0);
+        }
+    }
+}
@@ -8,7 +8,28 @@ use std::path::PathBuf;
 
 use crate::Error;
 
-use super::{Result, STORAGE_PREFIX};
+use super::{
+    target::private_address, Analytics, PooledConnection, Reason, Result, Tombstone, CLAIM_PREFIX,
+    EXPIRIES_KEY, OWNERS_KEY, OWNER_PREFIX, STORAGE_PREFIX,
+};
+
+/** The leading byte of the versioned encoding of the [`Record`]s, which the legacy encoding never starts with
+ * since it's first bytes are the `u32` tag of the [`RecordData`] variant */
+const ENCODING_MARKER: u8 = 0xff;
+
+/** The current version of the encoding of the [`Record`]s, to be bumped along any change of their layout */
+const ENCODING_VERSION: u8 = 4;
+
+/** Duration of the claim on a custom slug, covering the creation of it's [`Record`], in seconds */
+const CLAIM_LEASE: u64 = 10 * 60;
+
+/** Claim `KEYS[1]` for the holder `ARGV[1]` during `ARGV[2]` seconds, unless someone else holds it */
+const CLAIM_SCRIPT: &str = r"
+if redis.call('SET', KEYS[1], ARGV[1], 'NX', 'EX', ARGV[2]) then
+    return 1
+end
+return redis.call('GET', KEYS[1]) == ARGV[1] and 1 or 0
+";
 
 /** Represents a record with it's params and data */
 #[derive(Serialize, Deserialize, Clone)]
@@ -21,6 +42,145 @@ pub struct Record {
     accesses: Option<u16>,
     /** Date of expiry, if applicable */
     expiry: Option<DateTime<Utc>>,
+    /** Whether the [`Record`] must be explicitly revealed, so it's not consumed by link previews */
+    burn: bool,
+    /** Whether the [`Record`] is only served through signed, time-limited links */
+    signed: bool,
+    /** Name of the API key the [`Record`] was created with, if any */
+    owner: Option<String>,
+}
+
+/** The layout of the [`RecordData`] persisted before the url redirects had a status */
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+enum LegacyRecordData {
+    File {
+        name: String,
+        path: PathBuf,
+        size: usize,
+    },
+    Url {
+        target: rocket::http::uri::Absolute<'static>,
+    },
+    Paste {
+        body: String,
+    },
+}
+
+impl From<LegacyRecordData> for RecordData {
+    fn from(data: LegacyRecordData) -> Self {
+        match data {
+            LegacyRecordData::File { name, path, size } => RecordData::File { name, path, size },
+            /* Url redirects used to always answer with the default status */
+            LegacyRecordData::Url { target } => RecordData::Url {
+                target,
+                status: RedirectStatus::default(),
+            },
+            LegacyRecordData::Paste { body } => RecordData::Paste { body },
+        }
+    }
+}
+
+/** The layout of the [`Record`]s persisted before their encoding was versioned */
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+struct LegacyRecord {
+    data: LegacyRecordData,
+    slug: String,
+    accesses: Option<u16>,
+    expiry: Option<DateTime<Utc>>,
+}
+
+impl From<LegacyRecord> for Record {
+    fn from(legacy: LegacyRecord) -> Self {
+        Record {
+            data: legacy.data.into(),
+            slug: legacy.slug,
+            accesses: legacy.accesses,
+            expiry: legacy.expiry,
+            burn: false,
+            signed: false,
+            owner: None,
+        }
+    }
+}
+
+/** The layout of the version 1 of the [`Record`]s, before they had an owner */
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+struct RecordV1 {
+    data: LegacyRecordData,
+    slug: String,
+    accesses: Option<u16>,
+    expiry: Option<DateTime<Utc>>,
+    burn: bool,
+}
+
+impl From<RecordV1> for Record {
+    fn from(record: RecordV1) -> Self {
+        Record {
+            data: record.data.into(),
+            slug: record.slug,
+            accesses: record.accesses,
+            expiry: record.expiry,
+            burn: record.burn,
+            signed: false,
+            owner: None,
+        }
+    }
+}
+
+/** The layout of the version 2 of the [`Record`]s, before they could require a signature */
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+struct RecordV2 {
+    data: LegacyRecordData,
+    slug: String,
+    accesses: Option<u16>,
+    expiry: Option<DateTime<Utc>>,
+    burn: bool,
+    owner: Option<String>,
+}
+
+impl From<RecordV2> for Record {
+    fn from(record: RecordV2) -> Self {
+        Record {
+            data: record.data.into(),
+            slug: record.slug,
+            accesses: record.accesses,
+            expiry: record.expiry,
+            burn: record.burn,
+            signed: false,
+            owner: record.owner,
+        }
+    }
+}
+
+/** The layout of the version 3 of the [`Record`]s, before the url redirects had a status */
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+struct RecordV3 {
+    data: LegacyRecordData,
+    slug: String,
+    accesses: Option<u16>,
+    expiry: Option<DateTime<Utc>>,
+    burn: bool,
+    signed: bool,
+    owner: Option<String>,
+}
+
+impl From<RecordV3> for Record {
+    fn from(record: RecordV3) -> Self {
+        Record {
+            data: record.data.into(),
+            slug: record.slug,
+            accesses: record.accesses,
+            expiry: record.expiry,
+            burn: record.burn,
+            signed: record.signed,
+            owner: record.owner,
+        }
+    }
 }
 
 impl std::fmt::Debug for Record {
@@ -35,14 +195,16 @@ impl std::fmt::Debug for Record {
                     ByteUnit::from(*size)
                 )
             }
-            RecordData::Url { target } => write!(f, "Record::Url<{}>", target),
+            RecordData::Url { target, status } => {
+                write!(f, "Record::Url<{}, {}>", target, status.code())
+            }
             RecordData::Paste { body } => write!(f, "Record::Paste<{} chars>", body.len()),
         }?;
 
         write!(
             f,
-            " {{ accesses: {:?}, expiry: {:?} }}",
-            self.accesses, self.expiry
+            " {{ accesses: {:?}, expiry: {:?}, burn: {}, signed: {}, owner: {:?} }}",
+            self.accesses, self.expiry, self.burn, self.signed, self.owner
         )
     }
 }
@@ -63,6 +225,9 @@ impl Record {
             slug,
             accesses,
             expiry,
+            burn: false,
+            signed: false,
+            owner: None,
         }
     }
 
@@ -79,6 +244,9 @@ impl Record {
             slug,
             accesses,
             expiry,
+            burn: false,
+            signed: false,
+            owner: None,
         }
     }
 
@@ -86,15 +254,43 @@ impl Record {
     #[inline]
     pub const fn url(
         url: rocket::http::uri::Absolute<'static>,
+        status: RedirectStatus,
         slug: String,
         accesses: Option<u16>,
         expiry: Option<DateTime<Utc>>,
     ) -> Self {
         Record {
-            data: RecordData::Url { target: url },
+            data: RecordData::Url {
+                target: url,
+                status,
+            },
             slug,
             accesses,
             expiry,
+            burn: false,
+            signed: false,
+            owner: None,
+        }
+    }
+
+    /** Set whether the [`Record`] is burnt after reading, requiring an explicit confirmation to be revealed */
+    #[inline]
+    pub fn burn_after_reading(self, burn: bool) -> Self {
+        Record { burn, ..self }
+    }
+
+    /** Set whether the [`Record`] is only served through signed links, minted by it's owner */
+    #[inline]
+    pub fn require_signature(self, signed: bool) -> Self {
+        Record { signed, ..self }
+    }
+
+    /** Set the `owner` of the [`Record`], indexing it so it can be listed */
+    #[inline]
+    pub fn owned_by(self, owner: Option<&str>) -> Self {
+        Record {
+            owner: owner.map(String::from),
+            ..self
         }
     }
 
@@ -103,6 +299,39 @@ impl Record {
         [STORAGE_PREFIX, slug].concat()
     }
 
+    /** Encode the [`Record`] in it's versioned envelope */
+    fn encode(&self) -> crate::Result<Vec<u8>> {
+        let mut bytes = vec![ENCODING_MARKER, ENCODING_VERSION];
+        bincode::serialize_into(&mut bytes, self)?;
+
+        Ok(bytes)
+    }
+
+    /** Decode a [`Record`] from it's versioned envelope, or from the legacy layout it was persisted with */
+    fn decode(bytes: &[u8]) -> crate::Result<Self> {
+        match bytes {
+            [ENCODING_MARKER, ENCODING_VERSION, record @ ..] => Ok(bincode::deserialize(record)?),
+            [ENCODING_MARKER, 3, record @ ..] => {
+                Ok(bincode::deserialize::<RecordV3>(record)?.into())
+            }
+            [ENCODING_MARKER, 2, record @ ..] => {
+                Ok(bincode::deserialize::<RecordV2>(record)?.into())
+            }
+            [ENCODING_MARKER, 1, record @ ..] => {
+                Ok(bincode::deserialize::<RecordV1>(record)?.into())
+            }
+            [ENCODING_MARKER, version, ..] => Err(Error::Intrinsics(
+                format!("the record encoding version `{}` is unknown", version).into(),
+            )),
+            legacy => Ok(bincode::deserialize::<LegacyRecord>(legacy)?.into()),
+        }
+    }
+
+    #[inline]
+    fn owner_key(owner: &str) -> String {
+        [OWNER_PREFIX, owner].concat()
+    }
+
     /** Access the [`Record`]'s [`RecordData`] */
     #[inline]
     pub const fn data(&self) -> &RecordData {
@@ -115,18 +344,50 @@ impl Record {
         &self.slug
     }
 
+    /** Access the [`Record`]'s remaining number of accesses, if applicable */
+    #[inline]
+    pub const fn accesses(&self) -> Option<u16> {
+        self.accesses
+    }
+
+    /** Access the [`Record`]'s date of expiry, if applicable */
+    #[inline]
+    pub const fn expiry(&self) -> Option<DateTime<Utc>> {
+        self.expiry
+    }
+
+    /** Whether the [`Record`] is burnt after reading */
+    #[inline]
+    pub const fn is_burning(&self) -> bool {
+        self.burn
+    }
+
     /** Persist the [`Record`] to the Redis server */
-    pub async fn persist(&self, conn: &mut redis::aio::Connection) -> crate::Result<()> {
+    pub async fn persist(&self, conn: &mut PooledConnection) -> crate::Result<()> {
         use redis::AsyncCommands;
 
         /* Push the Record into Redis */
-        conn.set(Self::key(&self.slug), bincode::serialize(self)?)
+        conn.set::<_, _, ()>(Self::key(&self.slug), self.encode()?)
             .await?;
 
         if let Some(expiry) = self.expiry {
             /* Set it's expiry if required */
-            conn.expire_at(Self::key(&self.slug), expiry.timestamp() as usize)
-                .await?
+            conn.expire_at::<_, ()>(Self::key(&self.slug), expiry.timestamp() as usize)
+                .await?;
+
+            /* Index `file` records by expiry, for the quota's eviction policy */
+            if let RecordData::File { .. } = self.data {
+                conn.zadd::<_, _, _, ()>(EXPIRIES_KEY, &self.slug, expiry.timestamp())
+                    .await?
+            }
+        }
+
+        /* Index the record by owner, so it can be listed */
+        if let Some(owner) = &self.owner {
+            conn.sadd::<_, _, ()>(Self::owner_key(owner), &self.slug)
+                .await?;
+            conn.hset::<_, _, _, ()>(OWNERS_KEY, &self.slug, owner)
+                .await?;
         }
 
         Ok(())
@@ -134,28 +395,95 @@ impl Record {
 
     /** Delete the [`Record`] from the Redis server */
     #[inline]
-    pub async fn delete(&self, conn: &mut redis::aio::Connection) -> crate::Result<()> {
+    pub async fn delete(&self, conn: &mut PooledConnection) -> crate::Result<()> {
+        use redis::AsyncCommands;
+
+        conn.del::<_, ()>(Self::key(&self.slug)).await?;
+        Analytics::clear(&self.slug, &mut *conn).await?;
+
+        Self::disown(&self.slug, conn).await
+    }
+
+    /** Pin the [`Record`], so it never expires nor gets evicted */
+    pub async fn pin(self, conn: &mut PooledConnection) -> crate::Result<Self> {
+        use redis::AsyncCommands;
+
+        let record = Record {
+            expiry: None,
+            ..self
+        };
+
+        /* Overwriting the key drops it's expiry, and the index is what eviction works from */
+        record.persist(&mut *conn).await?;
+        conn.zrem::<_, _, ()>(EXPIRIES_KEY, &record.slug).await?;
+        Analytics::expire(&record, conn).await?;
+
+        Ok(record)
+    }
+
+    /** Remove the `slug` from it's owner's index, once the [`Record`] is gone */
+    pub async fn disown(slug: &str, conn: &mut PooledConnection) -> crate::Result<()> {
+        use redis::AsyncCommands;
+
+        if let Some(owner) = conn.hget::<_, _, Option<String>>(OWNERS_KEY, slug).await? {
+            conn.srem::<_, _, ()>(Self::owner_key(&owner), slug).await?;
+            conn.hdel::<_, _, ()>(OWNERS_KEY, slug).await?;
+        }
+
+        Ok(())
+    }
+
+    /** List the slugs of the [`Record`]s created by the `owner`, some of which may be gone */
+    pub async fn owned(owner: &str, conn: &mut PooledConnection) -> crate::Result<Vec<String>> {
         use redis::AsyncCommands;
 
-        Ok(conn.del(Self::key(&self.slug)).await?)
+        Ok(conn.smembers(Self::owner_key(owner)).await?)
+    }
+
+    /** Whether the [`Record`] is only served through signed links */
+    #[inline]
+    pub const fn is_signed(&self) -> bool {
+        self.signed
+    }
+
+    /** Access the [`Record`]'s `owner`, if any */
+    #[inline]
+    pub fn owner(&self) -> Option<&str> {
+        self.owner.as_deref()
     }
 
     /** Pull a [`Record`] from the Redis server from it's `slug` */
-    pub async fn fetch(
-        slug: &str,
-        conn: &mut redis::aio::Connection,
-    ) -> crate::Result<Option<Self>> {
+    pub async fn fetch(slug: &str, conn: &mut PooledConnection) -> crate::Result<Option<Self>> {
         use redis::AsyncCommands;
 
-        Ok(conn
-            .get::<_, Option<Vec<u8>>>(Self::key(slug))
+        conn.get::<_, Option<Vec<u8>>>(Self::key(slug))
             .await?
-            .map(|record| bincode::deserialize(&record))
-            .transpose()?)
+            .map(|record| Self::decode(&record))
+            .transpose()
+    }
+
+    /** Pull a [`Record`] from the Redis server from it's `slug`, or explain why it's not there */
+    pub async fn find(slug: &str, conn: &mut PooledConnection) -> crate::Result<Self> {
+        match Self::fetch(slug, &mut *conn).await? {
+            Some(record) => Ok(record),
+            None => Err(match Tombstone::fetch(slug, conn).await? {
+                /* Records taken down are unavailable for legal reasons, rather than just gone */
+                Some(tombstone) if tombstone.reason() == Reason::TakenDown => Error::TakenDown(
+                    slug.to_string(),
+                    tombstone
+                        .details()
+                        .unwrap_or("no details were given")
+                        .to_string(),
+                ),
+                Some(tombstone) => Error::Gone(slug.to_string(), tombstone.reason()),
+                None => Error::NotFound(slug.to_string()),
+            }),
+        }
     }
 
-    /** Consume this instance of the [`Record`], and update it's intrinsics to reflect the fact it has been accessed */
-    pub async fn consume(self, conn: &mut redis::aio::Connection) -> crate::Result<()> {
+    /** Consume this instance of the [`Record`], and update it's intrinsics to reflect the fact it has been accessed,
+     * returning whether it has been exhausted */
+    pub async fn consume(self, conn: &mut PooledConnection) -> crate::Result<bool> {
         let record = Record {
             /* Register a new access if needed */
             accesses: self.accesses.map(|count| count - 1),
@@ -165,7 +493,9 @@ impl Record {
         match record.accesses {
             Some(0) => {
                 tracing::trace!("Record has no accesses left, removing");
-                record.delete(&mut *conn).await?
+                record.delete(&mut *conn).await?;
+
+                return Ok(true);
             }
             Some(count) => {
                 tracing::trace!("Record has `{}` accesses left, pushing change", count);
@@ -174,16 +504,66 @@ impl Record {
             None => (),
         };
 
-        Ok(())
+        Ok(false)
     }
 
     /** Checks for the existence of a [`Record`] from it's `slug` in the server */
     #[inline]
-    pub async fn exists(slug: &str, conn: &mut redis::aio::Connection) -> Result<bool> {
+    pub async fn exists(slug: &str, conn: &mut PooledConnection) -> Result<bool> {
         use redis::AsyncCommands;
 
         Ok(conn.exists(Self::key(slug)).await?)
     }
+
+    /** Claim the `slug` for the `holder` during `lease` seconds, so that no one else creates a [`Record`] with it
+     * meanwhile, returning whether the `holder` has the claim */
+    pub async fn claim(
+        slug: &str,
+        holder: &str,
+        lease: u64,
+        conn: &mut PooledConnection,
+    ) -> Result<bool> {
+        Ok(redis::Script::new(CLAIM_SCRIPT)
+            .key([CLAIM_PREFIX, slug].concat())
+            .arg(holder)
+            .arg(lease)
+            .invoke_async::<_, u8>(conn)
+            .await?
+            == 1)
+    }
+
+    /** Give up the claim on the `slug`, once it's [`Record`] was created or could not be */
+    #[inline]
+    pub async fn unclaim(slug: &str, conn: &mut PooledConnection) -> Result<()> {
+        use redis::AsyncCommands;
+
+        Ok(conn.del([CLAIM_PREFIX, slug].concat()).await?)
+    }
+
+    /** Whether the `slug` is held by a [`Record`], or by the [`Tombstone`] of a dead one, so that a custom slug can't
+     * take the place of a record that was taken down */
+    pub async fn is_taken(slug: &str, conn: &mut PooledConnection) -> Result<bool> {
+        Ok(Self::exists(slug, &mut *conn).await? || Tombstone::exists(slug, conn).await?)
+    }
+}
+
+/** Represents the type of a [`Record`], without it's data */
+#[derive(Serialize, Deserialize, rocket::FromFormField, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordType {
+    File,
+    Paste,
+    Url,
+}
+
+impl std::fmt::Display for RecordType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            RecordType::File => "file",
+            RecordType::Paste => "paste",
+            RecordType::Url => "url",
+        })
+    }
 }
 
 /** Represents a record's data, or a link to it */
@@ -198,13 +578,101 @@ pub enum RecordData {
     /** Represents a URL redirect, see [`Record`] */
     Url {
         target: rocket::http::uri::Absolute<'static>,
+        status: RedirectStatus,
     },
     /** Represents a paste in utf-8, see [`Record`] */
     Paste { body: String },
 }
 
+impl RecordData {
+    /** Get the [`RecordType`] of the [`RecordData`] */
+    #[inline]
+    pub const fn kind(&self) -> RecordType {
+        match self {
+            RecordData::File { .. } => RecordType::File,
+            RecordData::Url { .. } => RecordType::Url,
+            RecordData::Paste { .. } => RecordType::Paste,
+        }
+    }
+
+    /** Get the size of the [`RecordData`], in bytes */
+    pub fn size(&self) -> usize {
+        match self {
+            RecordData::File { size, .. } => *size,
+            RecordData::Url { target, .. } => target.to_string().len(),
+            RecordData::Paste { body } => body.len(),
+        }
+    }
+}
+
+/** The status code of a url redirect, chosen upon it's creation */
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RedirectStatus {
+    /** `301 Moved Permanently` */
+    Moved,
+    /** `302 Found` */
+    Found,
+    /** `303 See Other`, the historical behavior */
+    #[default]
+    SeeOther,
+    /** `307 Temporary Redirect` */
+    Temporary,
+    /** `308 Permanent Redirect` */
+    Permanent,
+}
+
+impl std::str::FromStr for RedirectStatus {
+    type Err = String;
+
+    fn from_str(status: &str) -> std::result::Result<Self, Self::Err> {
+        match status.trim() {
+            "301" => Ok(RedirectStatus::Moved),
+            "302" => Ok(RedirectStatus::Found),
+            "303" => Ok(RedirectStatus::SeeOther),
+            "307" => Ok(RedirectStatus::Temporary),
+            "308" => Ok(RedirectStatus::Permanent),
+            _ => Err(format!("`{}` is not a redirect status", status)),
+        }
+    }
+}
+
+impl RedirectStatus {
+    /** Get the HTTP status code of the [`RedirectStatus`] */
+    pub const fn code(self) -> u16 {
+        match self {
+            RedirectStatus::Moved => 301,
+            RedirectStatus::Found => 302,
+            RedirectStatus::SeeOther => 303,
+            RedirectStatus::Temporary => 307,
+            RedirectStatus::Permanent => 308,
+        }
+    }
+
+    /** Whether the browsers may remember the redirect, and skip the server afterwards */
+    #[inline]
+    pub const fn is_permanent(self) -> bool {
+        matches!(self, RedirectStatus::Moved | RedirectStatus::Permanent)
+    }
+
+    /** Build the [`Redirect`](rocket::response::Redirect) to the `target` with this status */
+    pub fn redirect(
+        self,
+        target: rocket::http::uri::Absolute<'static>,
+    ) -> rocket::response::Redirect {
+        use rocket::response::Redirect;
+
+        match self {
+            RedirectStatus::Moved => Redirect::moved(target),
+            RedirectStatus::Found => Redirect::found(target),
+            RedirectStatus::SeeOther => Redirect::to(target),
+            RedirectStatus::Temporary => Redirect::temporary(target),
+            RedirectStatus::Permanent => Redirect::permanent(target),
+        }
+    }
+}
+
 /** Structure representing parameters regarding the configuration of [`Record`]s */
-#[derive(Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct RecordSettings {
     /** Maximum number of accesses before the removal of the record */
     max_access: Option<u16>,
@@ -214,10 +682,22 @@ pub struct RecordSettings {
     expire_in: Option<u64>,
     /** Desired `slug` length */
     slug_length: Option<u8>,
+    /** Desired style of the random `slug` */
+    slug_style: Option<super::SlugStyle>,
     /** Desired custom `slug` */
     custom_slug: Option<String>,
+    /** Status code of the url redirect */
+    redirect_status: Option<RedirectStatus>,
+    /** Whether a taken custom `slug` is refused rather than replaced by a random one */
+    slug_strict: Option<bool>,
     /** Checksum of the record to be verified upon upload */
     data_checksum: Option<String>,
+    /** Webhook notified of the record's lifecycle events */
+    notify_url: Option<String>,
+    /** Require an explicit confirmation to reveal the record, defaulting to a single access */
+    burn_after_reading: bool,
+    /** Only serve the record through signed, time-limited links */
+    require_signature: bool,
 }
 
 #[rocket::async_trait]
@@ -267,6 +747,16 @@ impl<'r> FromRequest<'r> for RecordSettings {
             Err(_) => return request::Outcome::Failure((Status::BadRequest, ())),
         };
 
+        let slug_style = match req
+            .headers()
+            .get_one("Slug-Style")
+            .map(str::parse)
+            .transpose()
+        {
+            Ok(data) => data,
+            Err(_) => return request::Outcome::Failure((Status::BadRequest, ())),
+        };
+
         let custom_slug = match req
             .headers()
             .get_one("Custom-Slug")
@@ -277,6 +767,26 @@ impl<'r> FromRequest<'r> for RecordSettings {
             Err(_) => return request::Outcome::Failure((Status::BadRequest, ())),
         };
 
+        let slug_strict = match req
+            .headers()
+            .get_one("Slug-Strict")
+            .map(str::parse)
+            .transpose()
+        {
+            Ok(data) => data,
+            Err(_) => return request::Outcome::Failure((Status::BadRequest, ())),
+        };
+
+        let redirect_status = match req
+            .headers()
+            .get_one("Redirect-Status")
+            .map(str::parse)
+            .transpose()
+        {
+            Ok(data) => data,
+            Err(_) => return request::Outcome::Failure((Status::BadRequest, ())),
+        };
+
         let data_checksum = match req
             .headers()
             .get_one("Data-Checksum")
@@ -287,6 +797,55 @@ impl<'r> FromRequest<'r> for RecordSettings {
             Err(_) => return request::Outcome::Failure((Status::BadRequest, ())),
         };
 
+        let burn_after_reading = match req
+            .headers()
+            .get_one("Burn-After-Reading")
+            .map(str::parse)
+            .transpose()
+        {
+            Ok(data) => data.unwrap_or_default(),
+            Err(_) => return request::Outcome::Failure((Status::BadRequest, ())),
+        };
+
+        let require_signature = match req
+            .headers()
+            .get_one("Require-Signature")
+            .map(str::parse)
+            .transpose()
+        {
+            Ok(data) => data.unwrap_or_default(),
+            Err(_) => return request::Outcome::Failure((Status::BadRequest, ())),
+        };
+
+        /* Signed links can't be minted without a signing secret */
+        if require_signature
+            && req
+                .rocket()
+                .state::<crate::Config>()
+                .is_none_or(|config| config.signing_secret.is_none())
+        {
+            return request::Outcome::Failure((Status::BadRequest, ()));
+        }
+
+        /* The webhook must be an absolute `http` or `https` url, which can't reach into our private network */
+        let allow_private = req
+            .rocket()
+            .state::<crate::Config>()
+            .is_some_and(|config| config.url_allow_private);
+        let notify_url = match req.headers().get_one("Notify-Url") {
+            Some(url) => match rocket::http::uri::Absolute::parse(url) {
+                Ok(uri) if uri.scheme() == "http" || uri.scheme() == "https" => {
+                    if !allow_private && private_address(&uri).await.is_some() {
+                        return request::Outcome::Failure((Status::BadRequest, ()));
+                    }
+
+                    Some(url.to_string())
+                }
+                _ => return request::Outcome::Failure((Status::BadRequest, ())),
+            },
+            None => None,
+        };
+
         /* If the two collide, return a Failure, both cannot be defined at the same time */
         if expiry_timestamp.is_some() && expire_in.is_some() {
             return request::Outcome::Failure((Status::BadRequest, ()));
@@ -297,17 +856,62 @@ impl<'r> FromRequest<'r> for RecordSettings {
             expiry_timestamp,
             expire_in,
             slug_length,
+            slug_style,
             custom_slug,
+            slug_strict,
+            redirect_status,
             data_checksum,
+            notify_url,
+            burn_after_reading,
+            require_signature,
         })
     }
 }
 
 impl RecordSettings {
-    /** Extract the number of accesses from the [`RecordSettings`] */
+    /** Extract the number of accesses from the [`RecordSettings`], a burnt record is accessed once by default */
     #[inline]
-    pub const fn accesses(&self) -> Option<u16> {
-        self.max_access
+    pub fn accesses(&self) -> Option<u16> {
+        match self.max_access {
+            None if self.burn_after_reading => Some(1),
+            accesses => accesses,
+        }
+    }
+
+    /** Extract whether the record is burnt after reading from the [`RecordSettings`] */
+    #[inline]
+    pub const fn burn(&self) -> bool {
+        self.burn_after_reading
+    }
+
+    /** Extract whether the record is only served through signed links from the [`RecordSettings`] */
+    #[inline]
+    pub const fn signed(&self) -> bool {
+        self.require_signature
+    }
+
+    /** Extract the desired custom slug from the [`RecordSettings`], if any */
+    #[inline]
+    pub fn custom_slug(&self) -> Option<&str> {
+        self.custom_slug.as_deref()
+    }
+
+    /** Whether a taken custom slug is refused, from the [`RecordSettings`] or the server-wide default */
+    #[inline]
+    pub fn strict(&self, config: &crate::Config) -> bool {
+        self.slug_strict.unwrap_or(config.slug_strict)
+    }
+
+    /** Extract the status code of the url redirect from the [`RecordSettings`] */
+    #[inline]
+    pub fn redirect_status(&self) -> RedirectStatus {
+        self.redirect_status.unwrap_or_default()
+    }
+
+    /** Extract the record's own webhook from the [`RecordSettings`] */
+    #[inline]
+    pub fn notify(&self) -> Option<&str> {
+        self.notify_url.as_deref()
     }
 
     /** Compute the expiry from the [`RecordSettings`] and an optionnal `max_age` */
@@ -329,39 +933,191 @@ impl RecordSettings {
         timestamp.map(|ts| DateTime::from_utc(NaiveDateTime::from_timestamp(ts as i64, 0), Utc))
     }
 
-    /** Compute the slug from the [`RecordSettings`] and [`Config`] and ensure it's not colliding */
+    /** Compute the expiry from the [`RecordSettings`], falling back to the `default_age` and capped by the `max_age` */
+    pub fn expiry_or(&self, default_age: u64, max_age: u64) -> DateTime<Utc> {
+        let max_age = match (self.expiry_timestamp, self.expire_in) {
+            (None, None) => u64::min(default_age, max_age),
+            _ => max_age,
+        };
+
+        self.expiry(Some(max_age)).unwrap() // <- unwrap here is safe, because the Option conditioned by the `max_age` parameter
+    }
+
+    /** Compute the slug from the [`RecordSettings`] and [`Config`] and ensure it's not colliding,
+     * refusing the custom slugs not following the `grammar`, and claiming them for the `holder` of a previous claim
+     * if any, like a resumable upload */
     pub async fn slug(
         &self,
+        grammar: &super::SlugGrammar,
         config: &crate::Config,
-        conn: &mut redis::aio::Connection,
+        holder: Option<&str>,
+        conn: &mut PooledConnection,
     ) -> Result<String> {
-        use rand::{distributions::Alphanumeric, Rng};
+        /* An empty custom slug falls back to a random one, like it always did */
+        if let Some(slug) = self.custom_slug.as_deref().filter(|slug| !slug.is_empty()) {
+            grammar.validate(slug)?;
 
-        Ok(match self.custom_slug {
-            /* If a custom slug exists, is not empty and does not exist, use it */
-            Some(ref slug) if !slug.is_empty() && !Record::exists(slug, &mut *conn).await? => {
-                slug.clone()
-            }
-            /* Else, generate a random slug of `max(<slug configured length>, <desired length>)` */
-            _ => {
-                let length =
-                    std::cmp::max(config.slug_length, self.slug_length.unwrap_or_default());
-
-                let slug: String = rand::thread_rng()
-                    .sample_iter(&Alphanumeric)
-                    .take(length as usize)
-                    .map(char::from)
-                    .collect();
-
-                // Check if the random generator made a collision
-                if Record::exists(&slug, &mut *conn).await? {
-                    return Err(Error::Intrinsics(
-                        "The randomly-generated slug already exists, this is unexpected".into(),
-                    ));
+            /* Claiming the slug first ensures no concurrent request creates a record with it, or writes to it's file */
+            let holder = holder
+                .map(String::from)
+                .unwrap_or_else(|| rand::random::<u64>().to_string());
+            if Record::claim(slug, &holder, CLAIM_LEASE, &mut *conn).await? {
+                if !Record::is_taken(slug, &mut *conn).await? {
+                    return Ok(slug.to_string());
                 }
 
-                slug
+                /* Otherwise the claim is of no use, and must not hold the slug any longer */
+                Record::unclaim(slug, &mut *conn).await?;
             }
-        })
+
+            /* A vanity link pointing to the wrong record is worse than none */
+            if self.strict(config) {
+                return Err(Error::SlugTaken(slug.to_string()));
+            }
+        }
+
+        /* Else, generate a random slug of at least `max(<slug configured length>, <desired length>)`,
+         * in the desired or configured style */
+        let length = std::cmp::max(config.slug_length, self.slug_length.unwrap_or_default());
+
+        let slug = self
+            .slug_style
+            .unwrap_or(config.slug_style)
+            .generate(config, length as usize)?;
+
+        // Check if the random generator made a collision
+        if Record::exists(&slug, &mut *conn).await? {
+            return Err(Error::Intrinsics(
+                "The randomly-generated slug already exists, this is unexpected".into(),
+            ));
+        }
+
+        Ok(slug)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_roundtrip_through_the_envelope() {
+        let record = Record::paste(String::from("body"), String::from("slug"), Some(3), None)
+            .burn_after_reading(true)
+            .require_signature(true)
+            .owned_by(Some("key"));
+
+        let decoded = Record::decode(&record.encode().unwrap()).unwrap();
+
+        assert_eq!(format!("{:?}", decoded), format!("{:?}", record));
+        assert_eq!(decoded.slug(), "slug");
+    }
+
+    #[test]
+    fn legacy_records_are_decoded() {
+        let legacy = LegacyRecord {
+            data: LegacyRecordData::Paste {
+                body: String::from("body"),
+            },
+            slug: String::from("slug"),
+            accesses: Some(3),
+            expiry: None,
+        };
+
+        let record = Record::decode(&bincode::serialize(&legacy).unwrap()).unwrap();
+
+        assert_eq!(record.slug(), "slug");
+        assert_eq!(record.accesses(), Some(3));
+        assert!(!record.is_burning() && !record.is_signed() && record.owner().is_none());
+    }
+
+    #[test]
+    fn version_1_records_are_decoded() {
+        let mut bytes = vec![ENCODING_MARKER, 1];
+        bincode::serialize_into(
+            &mut bytes,
+            &RecordV1 {
+                data: LegacyRecordData::Paste {
+                    body: String::from("body"),
+                },
+                slug: String::from("slug"),
+                accesses: None,
+                expiry: None,
+                burn: true,
+            },
+        )
+        .unwrap();
+
+        let record = Record::decode(&bytes).unwrap();
+
+        assert!(record.is_burning() && !record.is_signed() && record.owner().is_none());
+    }
+
+    #[test]
+    fn version_2_records_are_decoded() {
+        let mut bytes = vec![ENCODING_MARKER, 2];
+        bincode::serialize_into(
+            &mut bytes,
+            &RecordV2 {
+                data: LegacyRecordData::Paste {
+                    body: String::from("body"),
+                },
+                slug: String::from("slug"),
+                accesses: None,
+                expiry: None,
+                burn: false,
+                owner: Some(String::from("key")),
+            },
+        )
+        .unwrap();
+
+        let record = Record::decode(&bytes).unwrap();
+
+        assert!(!record.is_signed());
+        assert_eq!(record.owner(), Some("key"));
+    }
+
+    #[test]
+    fn version_3_redirects_get_the_default_status() {
+        let mut bytes = vec![ENCODING_MARKER, 3];
+        bincode::serialize_into(
+            &mut bytes,
+            &RecordV3 {
+                data: LegacyRecordData::Url {
+                    target: rocket::http::uri::Absolute::parse_owned(String::from(
+                        "https://example.com",
+                    ))
+                    .unwrap(),
+                },
+                slug: String::from("slug"),
+                accesses: None,
+                expiry: None,
+                burn: false,
+                signed: true,
+                owner: None,
+            },
+        )
+        .unwrap();
+
+        let record = Record::decode(&bytes).unwrap();
+
+        assert!(matches!(
+            record.data(),
+            RecordData::Url {
+                status: RedirectStatus::SeeOther,
+                ..
+            }
+        ));
+        assert!(record.is_signed());
+    }
+
+    #[test]
+    fn unknown_versions_are_refused() {
+        let mut bytes = Record::paste(String::new(), String::from("slug"), None, None)
+            .encode()
+            .unwrap();
+        bytes[1] = ENCODING_VERSION + 1;
+
+        assert!(matches!(Record::decode(&bytes), Err(Error::Intrinsics(_))));
     }
 }
@@ -1,10 +1,183 @@
 use rocket::data::ByteUnit;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
 use thiserror::Error;
 
+/** The steepness of the [`Shape::Exponential`] decay */
+const EXPONENTIAL_RATE: f64 = 3.0;
+
 #[derive(Debug, Error, PartialEq, Eq)]
 pub enum Error {
     #[error("The minimum age must be lower than the maximum age, but here {0} > {1}")]
     BoundDefinition(u64, u64),
+
+    #[error("The retention curve shape `{0}` is invalid, {1}")]
+    ShapeDefinition(String, &'static str),
+}
+
+/** The shape of the [`RetentionCurve`], plotting the retention from the size */
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub enum Shape {
+    /** The retention decreases linearly with the size */
+    Linear,
+    /** The retention decreases with the square of the size */
+    Quadratic,
+    /** The retention decreases with the cube of the size */
+    Cubic,
+    /** The retention decays exponentially with the size */
+    Exponential,
+    /** The retention is looked up in a table of `(size, age)` steps, and a fallback `age` */
+    Steps(Vec<(u64, u64)>, u64),
+}
+
+impl Shape {
+    /** Parse a duration such as `30d`, `12h`, `5m` or `45s` into seconds */
+    fn parse_duration(duration: &str) -> Option<u64> {
+        let duration = duration.trim();
+        let (value, unit) = duration.split_at(duration.find(|c: char| !c.is_ascii_digit())?);
+
+        value.parse::<u64>().ok().and_then(|value| {
+            Some(
+                value
+                    * match unit.trim() {
+                        "s" => 1,
+                        "m" => 60,
+                        "h" => 60 * 60,
+                        "d" => 60 * 60 * 24,
+                        "w" => 60 * 60 * 24 * 7,
+                        _ => return None,
+                    },
+            )
+        })
+    }
+
+    /** Format the `seconds` in the biggest unit that divides them */
+    pub fn format_duration(seconds: u64) -> String {
+        const UNITS: [(u64, &str); 4] = [
+            (60 * 60 * 24 * 7, "w"),
+            (60 * 60 * 24, "d"),
+            (60 * 60, "h"),
+            (60, "m"),
+        ];
+
+        UNITS
+            .iter()
+            .find(|(unit, _)| seconds != 0 && seconds.is_multiple_of(*unit))
+            .map(|(unit, suffix)| format!("{}{}", seconds / unit, suffix))
+            .unwrap_or_else(|| format!("{}s", seconds))
+    }
+
+    /** Parse a step table such as `<1MB: 30d, <50MB: 7d, else 1d` */
+    fn parse_steps(table: &str) -> Result<Self, Error> {
+        let invalid = |reason| Error::ShapeDefinition(table.to_string(), reason);
+
+        let mut steps = Vec::new();
+        let mut fallback = None;
+
+        for step in table.split(',').map(str::trim) {
+            if fallback.is_some() {
+                return Err(invalid("the `else` step must come last"));
+            }
+
+            if let Some(age) = step.strip_prefix("else") {
+                let age = age.trim_start().trim_start_matches(':');
+
+                fallback = Some(
+                    Self::parse_duration(age)
+                        .ok_or_else(|| invalid("a step has an invalid age"))?,
+                );
+            } else {
+                let (size, age) = step
+                    .strip_prefix('<')
+                    .and_then(|step| step.split_once(':'))
+                    .ok_or_else(|| invalid("steps must be formatted as `<size: age`"))?;
+
+                let size: ByteUnit = size
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid("a step has an invalid size"))?;
+                let age = Self::parse_duration(age)
+                    .ok_or_else(|| invalid("a step has an invalid age"))?;
+
+                if steps
+                    .last()
+                    .map(|(last, _)| *last >= size.as_u64())
+                    .unwrap_or(false)
+                {
+                    return Err(invalid("the step sizes must be increasing"));
+                }
+
+                steps.push((size.as_u64(), age));
+            }
+        }
+
+        Ok(Self::Steps(
+            steps,
+            fallback.ok_or_else(|| invalid("the table must end with an `else` step"))?,
+        ))
+    }
+}
+
+impl std::str::FromStr for Shape {
+    type Err = Error;
+
+    fn from_str(shape: &str) -> Result<Self, Self::Err> {
+        Ok(match shape.trim() {
+            "linear" => Self::Linear,
+            "quadratic" => Self::Quadratic,
+            "cubic" => Self::Cubic,
+            "exponential" => Self::Exponential,
+            table if table.starts_with('<') || table.starts_with("else") => {
+                Self::parse_steps(table)?
+            }
+            _ => {
+                return Err(Error::ShapeDefinition(
+                    shape.to_string(),
+                    "expected `linear`, `quadratic`, `cubic`, `exponential` or a step table",
+                ))
+            }
+        })
+    }
+}
+
+impl TryFrom<String> for Shape {
+    type Error = Error;
+
+    #[inline]
+    fn try_from(shape: String) -> Result<Self, Self::Error> {
+        shape.parse()
+    }
+}
+
+impl From<Shape> for String {
+    #[inline]
+    fn from(shape: Shape) -> Self {
+        shape.to_string()
+    }
+}
+
+impl std::fmt::Display for Shape {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Linear => f.write_str("linear"),
+            Self::Quadratic => f.write_str("quadratic"),
+            Self::Cubic => f.write_str("cubic"),
+            Self::Exponential => f.write_str("exponential"),
+            Self::Steps(steps, fallback) => {
+                for (size, age) in steps {
+                    write!(
+                        f,
+                        "<{}: {}, ",
+                        ByteUnit::from(*size),
+                        Self::format_duration(*age)
+                    )?;
+                }
+
+                write!(f, "else {}", Self::format_duration(*fallback))
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -12,12 +185,13 @@ pub struct RetentionCurve {
     min_age: u64,
     max_age: u64,
     max_size: u64,
+    shape: Shape,
 }
 
 impl RetentionCurve {
     /** Create a new [`RetentionCurve`] from it's parameters */
     #[inline]
-    pub const fn new(min_age: u64, max_age: u64, max_size: u64) -> Result<Self, Error> {
+    pub fn new(min_age: u64, max_age: u64, max_size: u64, shape: Shape) -> Result<Self, Error> {
         if min_age > max_age {
             return Err(Error::BoundDefinition(min_age, max_age));
         }
@@ -26,25 +200,64 @@ impl RetentionCurve {
             min_age,
             max_age,
             max_size,
+            shape,
         })
     }
 
     /** Get the max age in seconds of the [`RetentionCurve`]*/
     #[inline]
-    pub const fn max(&self) -> u64 {
-        self.max_age
+    pub fn max(&self) -> u64 {
+        match &self.shape {
+            Shape::Steps(steps, fallback) => {
+                steps.iter().map(|(_, age)| *age).fold(*fallback, u64::max)
+            }
+            _ => self.max_age,
+        }
     }
 
-    /** Compute the expiry time from the inner parameters */
-    pub fn compute_for(&self, size: u64) -> u64 {
-        /* If we are on a bound, there is nothing to plot */
-        if size > self.max_size {
-            return self.min_age;
+    /** Get the min age in seconds of the [`RetentionCurve`]*/
+    #[inline]
+    pub fn min(&self) -> u64 {
+        match &self.shape {
+            Shape::Steps(steps, fallback) => {
+                steps.iter().map(|(_, age)| *age).fold(*fallback, u64::min)
+            }
+            _ => self.min_age,
         }
+    }
 
+    /** Access the [`Shape`] of the [`RetentionCurve`] */
+    #[inline]
+    pub const fn shape(&self) -> &Shape {
+        &self.shape
+    }
+
+    /** Compute the expiry time from the inner parameters */
+    pub fn compute_for(&self, size: u64) -> u64 {
         let window = self.max_age - self.min_age;
+        let ratio = size as f64 / self.max_size as f64;
+
+        let plot = match &self.shape {
+            /* Step tables are not plotted, but looked up */
+            Shape::Steps(steps, fallback) => {
+                return steps
+                    .iter()
+                    .find(|(bound, _)| size < *bound)
+                    .map(|(_, age)| *age)
+                    .unwrap_or(*fallback)
+            }
+            /* If we are on a bound, there is nothing to plot */
+            _ if size > self.max_size => return self.min_age,
+            Shape::Linear => ratio,
+            Shape::Quadratic => f64::powi(ratio, 2),
+            Shape::Cubic => f64::powi(ratio, 3),
+            Shape::Exponential => {
+                /* Normalize the decay so that it spans the whole window */
+                let floor = f64::exp(-EXPONENTIAL_RATE);
 
-        let plot = f64::powi(size as f64 / self.max_size as f64, 2);
+                1.0 - (f64::exp(-EXPONENTIAL_RATE * ratio) - floor) / (1.0 - floor)
+            }
+        };
         let retention = self.max_age as f64 - window as f64 * plot;
 
         tracing::trace!(
@@ -53,7 +266,7 @@ impl RetentionCurve {
             ByteUnit::from(size)
         );
 
-        retention as u64
+        retention.round() as u64
     }
 }
 
@@ -63,38 +276,127 @@ mod tests {
 
     #[test]
     fn bound_definition_error() {
-        let error = RetentionCurve::new(1, 0, 0).unwrap_err();
+        let error = RetentionCurve::new(1, 0, 0, Shape::Quadratic).unwrap_err();
 
         assert_eq!(error, Error::BoundDefinition(1, 0));
     }
 
     #[test]
     fn zero_size_is_max_age() {
-        let curve = RetentionCurve::new(604800, 1814400, 128000000).unwrap();
+        let curve = RetentionCurve::new(604800, 1814400, 128000000, Shape::Quadratic).unwrap();
 
         assert_eq!(curve.compute_for(0), 1814400);
     }
 
     #[test]
     fn max_size_is_min_age() {
-        let curve = RetentionCurve::new(604800, 1814400, 128000000).unwrap();
+        let curve = RetentionCurve::new(604800, 1814400, 128000000, Shape::Quadratic).unwrap();
 
         assert_eq!(curve.compute_for(128000000), 604800);
     }
 
     #[test]
     fn more_than_max_size_is_min_age() {
-        let curve = RetentionCurve::new(604800, 1814400, 128000000).unwrap();
+        let curve = RetentionCurve::new(604800, 1814400, 128000000, Shape::Quadratic).unwrap();
 
         assert_eq!(curve.compute_for(512000000), 604800);
     }
 
     #[test]
     fn curve_is_exponential() {
-        let curve = RetentionCurve::new(0, 10000, 10000).unwrap();
+        let curve = RetentionCurve::new(0, 10000, 10000, Shape::Quadratic).unwrap();
 
         assert_eq!(curve.compute_for(2500), 9375);
         assert_eq!(curve.compute_for(5000), 7500);
         assert_eq!(curve.compute_for(7500), 4375);
     }
+
+    #[test]
+    fn every_shape_spans_the_bounds() {
+        for shape in [
+            Shape::Linear,
+            Shape::Quadratic,
+            Shape::Cubic,
+            Shape::Exponential,
+        ] {
+            let curve = RetentionCurve::new(604800, 1814400, 128000000, shape).unwrap();
+
+            assert_eq!(curve.compute_for(0), 1814400);
+            assert_eq!(curve.compute_for(128000000), 604800);
+            assert_eq!(curve.compute_for(512000000), 604800);
+        }
+    }
+
+    #[test]
+    fn curve_is_linear() {
+        let curve = RetentionCurve::new(0, 10000, 10000, Shape::Linear).unwrap();
+
+        assert_eq!(curve.compute_for(2500), 7500);
+        assert_eq!(curve.compute_for(5000), 5000);
+        assert_eq!(curve.compute_for(7500), 2500);
+    }
+
+    #[test]
+    fn curve_is_cubic() {
+        let curve = RetentionCurve::new(0, 10000, 10000, Shape::Cubic).unwrap();
+
+        assert_eq!(curve.compute_for(2500), 9844);
+        assert_eq!(curve.compute_for(5000), 8750);
+        assert_eq!(curve.compute_for(7500), 5781);
+    }
+
+    #[test]
+    fn curve_is_exponential_decay() {
+        let curve = RetentionCurve::new(0, 10000, 10000, Shape::Exponential).unwrap();
+
+        assert_eq!(curve.compute_for(2500), 4447);
+        assert_eq!(curve.compute_for(5000), 1824);
+        assert_eq!(curve.compute_for(7500), 585);
+    }
+
+    #[test]
+    fn curve_is_a_step_table() {
+        let shape = "<1MB: 30d, <50MB: 7d, else 1d".parse().unwrap();
+        let curve = RetentionCurve::new(604800, 1814400, 128000000, shape).unwrap();
+
+        assert_eq!(curve.compute_for(0), 2592000);
+        assert_eq!(curve.compute_for(999999), 2592000);
+        assert_eq!(curve.compute_for(1000000), 604800);
+        assert_eq!(curve.compute_for(49999999), 604800);
+        assert_eq!(curve.compute_for(50000000), 86400);
+        assert_eq!(curve.compute_for(512000000), 86400);
+
+        assert_eq!(curve.max(), 2592000);
+        assert_eq!(curve.min(), 86400);
+    }
+
+    #[test]
+    fn step_table_definition_errors() {
+        for table in [
+            "<1MB: 30d",
+            "<50MB: 7d, <1MB: 30d, else 1d",
+            "<1XB: 30d, else 1d",
+            "<1MB: 30y, else 1d",
+            "1MB: 30d, else 1d",
+            "else 1d, <1MB: 30d",
+        ] {
+            assert!(matches!(
+                table.parse::<Shape>(),
+                Err(Error::ShapeDefinition(..))
+            ));
+        }
+    }
+
+    #[test]
+    fn shapes_roundtrip_through_strings() {
+        for shape in [
+            "linear",
+            "quadratic",
+            "cubic",
+            "exponential",
+            "<1MB: 30d, <50MB: 12h, else 90s",
+        ] {
+            assert_eq!(shape.parse::<Shape>().unwrap().to_string(), shape);
+        }
+    }
 }
@@ -0,0 +1,120 @@
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use super::{Error, Result};
+
+/** Signs and verifies the time-limited links of the records requiring a signature */
+#[derive(Debug)]
+pub struct Signer {
+    secret: String,
+}
+
+impl Signer {
+    /** Create a new [`Signer`] from the server's `secret` */
+    #[inline]
+    pub const fn new(secret: String) -> Self {
+        Self { secret }
+    }
+
+    fn mac(&self, slug: &str, exp: i64) -> Hmac<Sha256> {
+        /* HMAC accepts keys of any length, so this can't fail */
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes()).unwrap();
+        mac.update(format!("{}:{}", slug, exp).as_bytes());
+
+        mac
+    }
+
+    /** Compute the hex-encoded signature of the link to `slug`, valid until the `exp` timestamp */
+    pub fn sign(&self, slug: &str, exp: i64) -> String {
+        hex::encode(self.mac(slug, exp).finalize().into_bytes())
+    }
+
+    /** Ensure the link to `slug` carries a valid and unexpired signature */
+    pub fn verify(&self, slug: &str, exp: Option<i64>, sig: Option<&str>) -> Result<()> {
+        let invalid = || Error::Forbidden(String::from("this link is invalid or has expired"));
+
+        let (exp, sig) = match (exp, sig) {
+            (Some(exp), Some(sig)) => (exp, hex::decode(sig).map_err(|_| invalid())?),
+            _ => return Err(invalid()),
+        };
+
+        if exp <= Utc::now().timestamp() {
+            return Err(invalid());
+        }
+
+        /* `verify_slice` compares in constant time */
+        self.mac(slug, exp)
+            .verify_slice(&sig)
+            .map_err(|_| invalid())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signer() -> Signer {
+        Signer::new(String::from("secret"))
+    }
+
+    fn future() -> i64 {
+        Utc::now().timestamp() + 60
+    }
+
+    #[test]
+    fn signed_links_are_verified() {
+        let exp = future();
+        let sig = signer().sign("slug", exp);
+
+        assert!(signer().verify("slug", Some(exp), Some(&sig)).is_ok());
+    }
+
+    #[test]
+    fn expired_links_are_refused() {
+        let exp = Utc::now().timestamp() - 1;
+        let sig = signer().sign("slug", exp);
+
+        assert!(matches!(
+            signer().verify("slug", Some(exp), Some(&sig)),
+            Err(Error::Forbidden(_))
+        ));
+    }
+
+    #[test]
+    fn tampered_links_are_refused() {
+        let exp = future();
+        let sig = signer().sign("slug", exp);
+
+        for (slug, exp, sig) in [
+            /* Another record */
+            ("other", Some(exp), Some(sig.clone())),
+            /* A pushed back expiry */
+            ("slug", Some(exp + 3600), Some(sig.clone())),
+            /* Another secret */
+            (
+                "slug",
+                Some(exp),
+                Some(Signer::new(String::from("other")).sign("slug", exp)),
+            ),
+            /* A forged or malformed signature */
+            ("slug", Some(exp), Some(sig.replace(&sig[..2], "00"))),
+            ("slug", Some(exp), Some(String::from("not hex"))),
+            ("slug", Some(exp), Some(sig[..32].to_string())),
+            /* Missing parameters */
+            ("slug", None, Some(sig.clone())),
+            ("slug", Some(exp), None),
+        ] {
+            assert!(
+                matches!(
+                    signer().verify(slug, exp, sig.as_deref()),
+                    Err(Error::Forbidden(_))
+                ),
+                "{} {:?} {:?}",
+                slug,
+                exp,
+                sig
+            );
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use rand::seq::SliceRandom;
+use rocket::Route;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+use super::{Error, Result};
+
+/** The diceware-style wordlist of the `words` slugs, made of 1024 short and common words */
+const WORDS: &str = include_str!("words.txt");
+
+/** The styles of the random slugs */
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SlugStyle {
+    /** Letters and digits, like `aZ3kPq9xT2bLw` */
+    Alphanumeric,
+    /** Characters of the configured `slug_alphabet`, which leaves out `0/O/o` and `1/l/I` by default */
+    Unambiguous,
+    /** Words of the wordlist, like `brave-otter-lamp` */
+    Words,
+}
+
+impl std::str::FromStr for SlugStyle {
+    type Err = String;
+
+    fn from_str(style: &str) -> std::result::Result<Self, Self::Err> {
+        match style.trim().to_lowercase().as_str() {
+            "alphanumeric" => Ok(SlugStyle::Alphanumeric),
+            "unambiguous" => Ok(SlugStyle::Unambiguous),
+            "words" => Ok(SlugStyle::Words),
+            _ => Err(format!("unknown slug style `{}`", style)),
+        }
+    }
+}
+
+impl SlugStyle {
+    /** Generate a random slug reaching the configured entropy, and at least `length` characters long
+     * for the styles drawing characters */
+    pub fn generate(self, config: &crate::Config, length: usize) -> Result<String> {
+        let mut rng = rand::thread_rng();
+
+        let alphabet: Vec<char> = match self {
+            SlugStyle::Alphanumeric => ('A'..='Z').chain('a'..='z').chain('0'..='9').collect(),
+            SlugStyle::Unambiguous => config
+                .slug_alphabet
+                .chars()
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect(),
+            SlugStyle::Words => {
+                let words: Vec<&str> = WORDS.lines().collect();
+                let count = symbols(config.slug_entropy, words.len());
+
+                return Ok((0..count)
+                    .filter_map(|_| words.choose(&mut rng).copied())
+                    .collect::<Vec<_>>()
+                    .join("-"));
+            }
+        };
+
+        if alphabet.len() < 2 {
+            return Err(Error::Intrinsics(
+                "The slug alphabet must have at least two distinct characters".into(),
+            ));
+        }
+
+        let length = length.max(symbols(config.slug_entropy, alphabet.len()));
+
+        Ok((0..length)
+            .filter_map(|_| alphabet.choose(&mut rng))
+            .collect())
+    }
+}
+
+/** Compute the number of symbols drawn among `size` ones needed to reach `bits` of entropy */
+fn symbols(bits: u32, size: usize) -> usize {
+    (bits as f64 / (size as f64).log2()).ceil() as usize
+}
+
+/** The grammar of the custom slugs, along with the words reserved by the mounted routes */
+#[derive(Debug, Clone)]
+pub struct SlugGrammar {
+    /** Characters allowed in the custom slugs */
+    charset: String,
+    /** Minimum length of the custom slugs, in characters */
+    min_length: usize,
+    /** Maximum length of the custom slugs, in characters */
+    max_length: usize,
+    /** Lowercased words that can't be used as slugs */
+    reserved: BTreeSet<String>,
+}
+
+impl SlugGrammar {
+    /** Create a new [`SlugGrammar`] from the [`Config`](crate::Config), reserving the first segments of the `routes` */
+    pub fn new<'r>(config: &crate::Config, routes: impl Iterator<Item = &'r Route>) -> Self {
+        let reserved = routes
+            .filter_map(|route| {
+                route
+                    .uri
+                    .path()
+                    .split('/')
+                    .find(|segment| !segment.is_empty())
+                    .filter(|segment| !segment.starts_with('<'))
+                    .map(String::from)
+            })
+            .chain(config.reserved_slugs.iter().cloned())
+            .map(|word| word.to_lowercase())
+            .collect();
+
+        Self {
+            charset: config.slug_charset.clone(),
+            min_length: config.slug_min_length as usize,
+            max_length: config.slug_max_length as usize,
+            reserved,
+        }
+    }
+
+    /** Ensure the custom `slug` follows the [`SlugGrammar`], and explain why if it does not */
+    pub fn validate(&self, slug: &str) -> Result<()> {
+        let refuse = |reason: String| Err(Error::InvalidSlug(slug.to_string(), reason));
+        let length = slug.chars().count();
+
+        if length < self.min_length || length > self.max_length {
+            return refuse(format!(
+                "it must be between {} and {} characters long",
+                self.min_length, self.max_length
+            ));
+        }
+
+        /* Slugs are also used as filenames, so the paths are never allowed whatever the charset */
+        if slug.contains(['/', '\\']) || slug == "." || slug == ".." {
+            return refuse(String::from("it must not be a path"));
+        }
+
+        if let Some(character) = slug.chars().find(|c| !self.charset.contains(*c)) {
+            return refuse(format!(
+                "the character `{}` is not allowed, only `{}` are",
+                character.escape_default(),
+                self.charset
+            ));
+        }
+
+        if self.reserved.contains(&slug.to_lowercase()) {
+            return refuse(String::from("it's a reserved word"));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /** Build the [`SlugGrammar`] of the `config`, reserving the words of the mounted API routes */
+    fn grammar(config: &crate::Config) -> SlugGrammar {
+        SlugGrammar::new(config, crate::api::routes().iter())
+    }
+
+    /** The reason why the `slug` is refused by the `grammar`, if it is */
+    fn refusal(grammar: &SlugGrammar, slug: &str) -> Option<String> {
+        match grammar.validate(slug) {
+            Err(Error::InvalidSlug(_, reason)) => Some(reason),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn valid_slugs_are_accepted() {
+        let grammar = grammar(&crate::Config::default());
+
+        for slug in &["abc", "my-slug_42", "a".repeat(64).as_str()] {
+            assert!(grammar.validate(slug).is_ok(), "{}", slug);
+        }
+    }
+
+    #[test]
+    fn lengths_are_bounded() {
+        let grammar = grammar(&crate::Config::default());
+
+        for slug in &["", "ab", "a".repeat(65).as_str()] {
+            assert!(refusal(&grammar, slug).is_some_and(|reason| reason.contains("between")));
+        }
+    }
+
+    #[test]
+    fn characters_follow_the_charset() {
+        let grammar = grammar(&crate::Config::default());
+
+        for slug in &["with space", "dot.ted", "émoji", "quest?on"] {
+            assert!(
+                refusal(&grammar, slug).is_some_and(|reason| reason.contains("is not allowed")),
+                "{}",
+                slug
+            );
+        }
+    }
+
+    #[test]
+    fn paths_are_refused_whatever_the_charset() {
+        let config = crate::Config {
+            slug_charset: String::from("abc./\\"),
+            slug_min_length: 1,
+            ..crate::Config::default()
+        };
+        let grammar = grammar(&config);
+
+        for slug in &[".", "..", "a/b", "a\\b", "../abc"] {
+            assert_eq!(
+                refusal(&grammar, slug).as_deref(),
+                Some("it must not be a path"),
+                "{}",
+                slug
+            );
+        }
+        assert!(grammar.validate("a.b").is_ok());
+    }
+
+    #[test]
+    fn reserved_words_are_refused() {
+        let config = crate::Config {
+            reserved_slugs: vec![String::from("Login")],
+            ..crate::Config::default()
+        };
+        let grammar = grammar(&config);
+
+        /* The configured words, and the first static segments of the routes, in any case */
+        for slug in &["login", "LOGIN", "api", "admin", "paste", "URL", "upload"] {
+            assert_eq!(
+                refusal(&grammar, slug).as_deref(),
+                Some("it's a reserved word"),
+                "{}",
+                slug
+            );
+        }
+
+        /* The dynamic segments are not words */
+        assert!(grammar.validate("stats").is_ok());
+        assert!(grammar.validate("link").is_ok());
+    }
+
+    /** The entropy of the `slug` generated in the `style`, in bits */
+    fn entropy(style: SlugStyle, config: &crate::Config, slug: &str) -> f64 {
+        let (count, size) = match style {
+            SlugStyle::Alphanumeric => (slug.chars().count(), 62),
+            SlugStyle::Unambiguous => (
+                slug.chars().count(),
+                config.slug_alphabet.chars().collect::<BTreeSet<_>>().len(),
+            ),
+            SlugStyle::Words => (slug.split('-').count(), WORDS.lines().count()),
+        };
+
+        count as f64 * (size as f64).log2()
+    }
+
+    #[test]
+    fn generated_slugs_follow_the_grammar() {
+        for bits in &[32, 64] {
+            let config = crate::Config {
+                slug_entropy: *bits,
+                ..crate::Config::default()
+            };
+            let grammar = grammar(&config);
+
+            for style in &[
+                SlugStyle::Alphanumeric,
+                SlugStyle::Unambiguous,
+                SlugStyle::Words,
+            ] {
+                for _ in 0..64 {
+                    let slug = style
+                        .generate(&config, config.slug_length as usize)
+                        .unwrap();
+
+                    assert!(grammar.validate(&slug).is_ok(), "{}", slug);
+                    assert!(
+                        entropy(*style, &config, &slug) >= *bits as f64,
+                        "{} has less than {} bits",
+                        slug,
+                        bits
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn short_alphabets_are_refused() {
+        let config = crate::Config {
+            slug_alphabet: String::from("aaa"),
+            ..crate::Config::default()
+        };
+
+        assert!(SlugStyle::Unambiguous.generate(&config, 13).is_err());
+    }
+}
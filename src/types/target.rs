@@ -0,0 +1,293 @@
+use rocket::http::uri::Absolute;
+use std::net::{IpAddr, Ipv4Addr};
+
+use super::{Error, Result};
+
+/** List the `host` and it's parent domains, `a.b.example.com` being itself, `b.example.com`, `example.com`
+ * and `com` */
+pub fn domains(host: &str) -> Vec<&str> {
+    std::iter::once(host)
+        .chain(host.match_indices('.').map(|(index, _)| &host[index + 1..]))
+        .collect()
+}
+
+/** The policy on the targets of the url redirects */
+#[derive(Debug)]
+pub struct TargetPolicy<'c> {
+    /** Domains the targets are restricted to, including their subdomains, any if empty */
+    allowed: &'c [String],
+    /** Domains the targets may not point to, including their subdomains */
+    denied: &'c [String],
+    /** Whether the targets may point to loopback, private or link-local addresses */
+    allow_private: bool,
+}
+
+impl<'c> TargetPolicy<'c> {
+    /** Create a new [`TargetPolicy`] from it's parameters */
+    #[inline]
+    pub const fn new(allowed: &'c [String], denied: &'c [String], allow_private: bool) -> Self {
+        Self {
+            allowed,
+            denied,
+            allow_private,
+        }
+    }
+
+    /** Ensure the `target` complies with the [`TargetPolicy`], and does not point back at our own `host` */
+    pub async fn check(&self, target: &Absolute<'_>, host: Option<&str>) -> Result<()> {
+        let authority = target.authority().ok_or_else(|| {
+            Error::UrlCreation("The url must contain at least a scheme and an authority".into())
+        })?;
+
+        /* Credentials would be disclosed to anyone following the redirect */
+        if authority.user_info().is_some() {
+            return Err(Error::UrlCreation(
+                "The url must not contain credentials".into(),
+            ));
+        }
+
+        let target_host = normalize(authority.host());
+
+        if host.map(normalize).as_deref() == Some(target_host.as_str()) {
+            return Err(Error::UrlCreation(
+                "The url must not point back at this server, as it would chain redirects".into(),
+            ));
+        }
+
+        if !self.allow_private {
+            if let Some(address) = private_address(target).await {
+                return Err(Error::UrlCreation(format!(
+                    "The url must not point to the private address `{}`",
+                    address
+                )));
+            }
+        }
+
+        let domains = domains(&target_host);
+        let listed = |list: &[String]| {
+            list.iter()
+                .find(|entry| domains.contains(&normalize(entry).as_str()))
+                .cloned()
+        };
+
+        if let Some(domain) = listed(self.denied) {
+            return Err(Error::UrlCreation(format!(
+                "The domain `{}` is not allowed",
+                domain
+            )));
+        }
+
+        if !self.allowed.is_empty() && listed(self.allowed).is_none() {
+            return Err(Error::UrlCreation(format!(
+                "The domain `{}` is not in the allowed domains",
+                target_host
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/** Normalize the `host` for comparisons, ignoring it's case, trailing dot and the IPv6 brackets */
+fn normalize(host: &str) -> String {
+    host.trim_start_matches('[')
+        .trim_end_matches(']')
+        .trim_end_matches('.')
+        .to_lowercase()
+}
+
+/** Find the loopback, private, link-local or unspecified address the `target` points to, be it written in the url
+ * or resolved from it's host, so that a public name can't be used to reach a private address */
+pub async fn private_address(target: &Absolute<'_>) -> Option<String> {
+    let authority = target.authority()?;
+    let host = normalize(authority.host());
+
+    if is_private(&host) {
+        return Some(host);
+    }
+
+    let port = authority
+        .port()
+        .unwrap_or(if target.scheme() == "https" { 443 } else { 80 });
+
+    /* Hosts which don't resolve point nowhere, and their resolution is checked again wherever they are requested */
+    let resolved = tokio::net::lookup_host((host.as_str(), port)).await.ok()?;
+    let private = resolved
+        .map(|address| address.ip())
+        .find(|ip| is_private_ip(*ip))?;
+
+    Some(format!("{} ({})", host, private))
+}
+
+/** Parse the `host` as an IPv4 address the way resolvers do, accepting the decimal (`2130706433`), hexadecimal
+ * (`0x7f.1`), octal (`0177.0.0.1`) and shortened (`127.1`) forms along the dotted-decimal one */
+fn parse_ipv4(host: &str) -> Option<Ipv4Addr> {
+    let parts = host
+        .split('.')
+        .map(|part| match part {
+            "" => None,
+            "0" => Some(0),
+            hex if hex.starts_with("0x") || hex.starts_with("0X") => {
+                u32::from_str_radix(&hex[2..], 16).ok()
+            }
+            octal if octal.starts_with('0') => u32::from_str_radix(&octal[1..], 8).ok(),
+            decimal => decimal.parse().ok(),
+        })
+        .collect::<Option<Vec<u32>>>()?;
+
+    /* Every part is a byte, but the last one which fills the remaining bytes */
+    let (last, leading) = parts.split_last()?;
+    if leading.len() > 3 || leading.iter().any(|part| *part > 0xff) {
+        return None;
+    }
+
+    let bits = 8 * (4 - leading.len() as u32);
+    if bits < 32 && *last >> bits != 0 {
+        return None;
+    }
+
+    let address = leading
+        .iter()
+        .enumerate()
+        .fold(*last, |address, (index, part)| {
+            address | part << (24 - 8 * index)
+        });
+
+    Some(Ipv4Addr::from(address))
+}
+
+/** Whether the normalized `host` is a loopback, private, link-local or unspecified address */
+fn is_private(host: &str) -> bool {
+    if host == "localhost" || host.ends_with(".localhost") {
+        return true;
+    }
+
+    match host.parse::<IpAddr>() {
+        Ok(ip) => is_private_ip(ip),
+        Err(_) => parse_ipv4(host).is_some_and(|ip| is_private_ip(IpAddr::V4(ip))),
+    }
+}
+
+/** Whether the `ip` is a loopback, private, link-local or unspecified address */
+fn is_private_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_private_ip(IpAddr::V4(ip)),
+            None => {
+                let segment = ip.segments()[0];
+
+                ip.is_loopback()
+                    || ip.is_unspecified()
+                    /* Unique local `fc00::/7` and link-local `fe80::/10` */
+                    || (segment & 0xfe00) == 0xfc00
+                    || (segment & 0xffc0) == 0xfe80
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ipv4_forms_are_parsed() {
+        let loopback = Some(Ipv4Addr::new(127, 0, 0, 1));
+
+        for host in [
+            "127.0.0.1",
+            "2130706433",
+            "0x7f000001",
+            "0x7f.1",
+            "127.1",
+            "127.0.1",
+            "0177.0.0.1",
+            "0x7F.0.0.0x1",
+        ] {
+            assert_eq!(parse_ipv4(host), loopback, "{}", host);
+        }
+    }
+
+    #[test]
+    fn malformed_ipv4_are_refused() {
+        for host in [
+            "",
+            "127..1",
+            "127.0.0.0.1",
+            "256.0.0.1",
+            "127.0x1000000",
+            "4294967296",
+            "0x",
+            "08.0.0.1",
+            "example.com",
+        ] {
+            assert_eq!(parse_ipv4(host), None, "{}", host);
+        }
+    }
+
+    #[test]
+    fn private_hosts_are_recognized() {
+        for host in [
+            "localhost",
+            "api.localhost",
+            "127.0.0.1",
+            "2130706433",
+            "0x7f.1",
+            "127.1",
+            "10.1",
+            "0xa9fea9fe",
+            "192.168.1.1",
+            "0",
+            "::1",
+            "::ffff:127.0.0.1",
+            "::ffff:7f00:1",
+            "fd00::1",
+            "fe80::1",
+        ] {
+            assert!(is_private(host), "{}", host);
+        }
+    }
+
+    #[test]
+    fn public_hosts_are_allowed() {
+        for host in [
+            "example.com",
+            "93.184.216.34",
+            "1572395042",
+            "8.8",
+            "2606:4700::1111",
+        ] {
+            assert!(!is_private(host), "{}", host);
+        }
+    }
+
+    #[rocket::async_test]
+    async fn private_targets_are_refused() {
+        let policy = TargetPolicy::new(&[], &[], false);
+
+        for target in [
+            "http://2130706433/",
+            "http://0x7f.1:8080/admin",
+            "https://127.1/",
+            "http://[::ffff:127.0.0.1]/",
+        ] {
+            let target = Absolute::parse(target).unwrap();
+
+            assert!(
+                matches!(
+                    policy.check(&target, None).await,
+                    Err(Error::UrlCreation(_))
+                ),
+                "{}",
+                target
+            );
+        }
+    }
+}
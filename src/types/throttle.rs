@@ -0,0 +1,368 @@
+use chrono::Utc;
+use redis::AsyncCommands;
+use rocket::http::Header;
+
+use super::{Error, KeyPolicy, PooledConnection, Record, Result, THROTTLE_PREFIX};
+
+/** Length of the window of the uploaded bytes quota, in seconds */
+const BYTES_PERIOD: u64 = 60 * 60 * 24;
+/** Length of the window of the created records quota, in seconds */
+const RECORDS_PERIOD: u64 = 60 * 60;
+
+/** Check the sliding windows whose keys go by pairs of previous and current fixed windows, along with their
+ * `amount`, `limit`, `period` and `elapsed` arguments, and only count the amounts when none of the limits is exceeded,
+ * returning the position of the exceeded window and it's usage otherwise, or 0 followed by the new usage of every
+ * window */
+const WINDOWS_SCRIPT: &str = r"
+local windows, usage = #KEYS / 2, {}
+for i = 1, windows do
+    local amount, limit = tonumber(ARGV[4 * i - 3]), tonumber(ARGV[4 * i - 2])
+    local period, elapsed = tonumber(ARGV[4 * i - 1]), tonumber(ARGV[4 * i])
+    local previous = tonumber(redis.call('GET', KEYS[2 * i - 1]) or '0')
+    local current = tonumber(redis.call('GET', KEYS[2 * i]) or '0')
+    local used = math.floor(previous * (period - elapsed) / period) + current
+    if used + amount > limit then
+        return {i, used}
+    end
+    usage[i] = used + amount
+end
+for i = 1, windows do
+    redis.call('INCRBY', KEYS[2 * i], ARGV[4 * i - 3])
+    redis.call('EXPIRE', KEYS[2 * i], 2 * tonumber(ARGV[4 * i - 1]))
+end
+return {0, unpack(usage)}
+";
+
+/** The state of a quota, as advertised in the `RateLimit-*` headers */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /** The limit of the quota */
+    pub limit: u64,
+    /** Remaining amount before the limit is reached */
+    pub remaining: u64,
+    /** Delay before enough of the quota is available again, in seconds */
+    pub reset: u64,
+}
+
+impl RateLimit {
+    /** Get the `RateLimit-*` headers advertising the quota */
+    pub fn headers(&self) -> Vec<Header<'static>> {
+        vec![
+            Header::new("RateLimit-Limit", self.limit.to_string()),
+            Header::new("RateLimit-Remaining", self.remaining.to_string()),
+            Header::new("RateLimit-Reset", self.reset.to_string()),
+        ]
+    }
+
+    /** Whether the quota is closer to being exhausted than the `other` one, relatively to their limits */
+    fn is_tighter(&self, other: &Self) -> bool {
+        (self.remaining as u128 * other.limit as u128)
+            < (other.remaining as u128 * self.limit as u128)
+    }
+}
+
+/** The amounts counted in the rolling quotas by [`Throttle::check`], to be refunded if the record is not created */
+#[derive(Debug, Default)]
+pub struct Charge {
+    /** The keys of the fixed windows counting the amounts, along with them */
+    amounts: Vec<(String, u64)>,
+    /** The state of the tightest quota after the charge, if any */
+    limit: Option<RateLimit>,
+}
+
+impl Charge {
+    /** Get the state of the quota closest to being exhausted, to be advertised to the client */
+    #[inline]
+    pub const fn limit(&self) -> Option<RateLimit> {
+        self.limit
+    }
+}
+
+/** The rolling quotas of a client, being either an API key or an IP address */
+pub struct Throttle<'i> {
+    subject: &'i str,
+    policy: &'i KeyPolicy,
+}
+
+impl<'i> Throttle<'i> {
+    /** Create a new [`Throttle`] for the `subject`, from it's `policy` */
+    #[inline]
+    pub const fn new(subject: &'i str, policy: &'i KeyPolicy) -> Self {
+        Self { subject, policy }
+    }
+
+    /** Compute the key of the quota `name`, hash-tagged by subject so that all of it's keys share a Cluster slot */
+    #[inline]
+    fn key(&self, name: &str) -> String {
+        format!("{}{{{}}}:{}", THROTTLE_PREFIX, self.subject, name)
+    }
+
+    /** Ensure a record of `size` bytes may be created without exceeding any of the quotas, and count it in the rolling
+     * ones, so that concurrent requests can't overrun them, returning the [`Charge`] to refund if it's not created */
+    pub async fn check(&self, size: u64, conn: &mut PooledConnection) -> Result<Charge> {
+        if let Some(limit) = self.policy.max_active_records {
+            self.active(limit, &mut *conn).await?;
+        }
+
+        let windows: Vec<_> = vec![
+            ("bytes", BYTES_PERIOD, size, self.policy.max_bytes_per_day),
+            (
+                "records",
+                RECORDS_PERIOD,
+                1,
+                self.policy.max_records_per_hour,
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(name, period, amount, limit)| Some((name, period, amount, limit?)))
+        .collect();
+
+        match windows.is_empty() {
+            true => Ok(Charge::default()),
+            false => self.windows(&windows, conn).await,
+        }
+    }
+
+    /** Give back the amounts of the `charge` to the rolling quotas, once the record could not be created */
+    pub async fn refund(&self, charge: &Charge, conn: &mut PooledConnection) -> Result<()> {
+        /* The keys of a subject share a Cluster slot, so they can go together */
+        let mut pipe = redis::pipe();
+        for (key, amount) in &charge.amounts {
+            pipe.decr(key, *amount).ignore();
+        }
+
+        Ok(pipe.query_async(conn).await?)
+    }
+
+    /** Account for the newly created `record` in the active records, the rolling quotas counted it upon the check */
+    pub async fn track(&self, record: &Record, conn: &mut PooledConnection) -> Result<()> {
+        if let Some(expiry) = record.expiry() {
+            conn.zadd::<_, _, _, ()>(self.key("active"), record.slug(), expiry.timestamp())
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /** Check and count the `(name, period, amount, limit)` sliding windows, each estimated from the current and
+     * previous fixed windows by weighting the previous one by how much of it still overlaps */
+    async fn windows(
+        &self,
+        windows: &[(&str, u64, u64, u64)],
+        conn: &mut PooledConnection,
+    ) -> Result<Charge> {
+        let now = Utc::now().timestamp() as u64;
+
+        let script = redis::Script::new(WINDOWS_SCRIPT);
+        let mut invocation = script.prepare_invoke();
+        let mut amounts = Vec::with_capacity(windows.len());
+        for (name, period, amount, limit) in windows {
+            let window = now / period;
+            let key = self.key(&format!("{}:{}", name, window));
+
+            invocation
+                .key(self.key(&format!("{}:{}", name, window - 1)))
+                .key(&key)
+                .arg(*amount)
+                .arg(*limit)
+                .arg(*period)
+                .arg(now % period);
+            amounts.push((key, *amount));
+        }
+
+        let reply: Vec<u64> = invocation.invoke_async(conn).await?;
+        let (exceeded, used) = match reply.as_slice() {
+            [0, usage @ ..] => (None, usage),
+            [exceeded, used] => (Some(*exceeded as usize), std::slice::from_ref(used)),
+            _ => {
+                return Err(Error::Intrinsics(
+                    "The quotas script replied unexpectedly".into(),
+                ))
+            }
+        };
+
+        match exceeded.and_then(|exceeded| windows.get(exceeded.wrapping_sub(1))) {
+            Some((name, period, _, limit)) => Err(Error::TooManyRequests(
+                format!(
+                    "the quota of {} {} per {} seconds was reached",
+                    limit, name, period
+                ),
+                RateLimit {
+                    limit: *limit,
+                    remaining: limit.saturating_sub(used[0]),
+                    reset: period - now % period,
+                },
+            )),
+            None => Ok(Charge {
+                amounts,
+                limit: windows
+                    .iter()
+                    .zip(used)
+                    .map(|((_, period, _, limit), used)| RateLimit {
+                        limit: *limit,
+                        remaining: limit.saturating_sub(*used),
+                        reset: period - now % period,
+                    })
+                    .reduce(|tightest, limit| match limit.is_tighter(&tightest) {
+                        true => limit,
+                        false => tightest,
+                    }),
+            }),
+        }
+    }
+
+    /** Check the number of active records against the `limit`, forgetting the ones that are gone */
+    async fn active(&self, limit: u64, conn: &mut PooledConnection) -> Result<()> {
+        let key = self.key("active");
+        let now = Utc::now().timestamp();
+
+        conn.zrembyscore::<_, _, _, ()>(&key, "-inf", now).await?;
+
+        if conn.zcard::<_, u64>(&key).await? >= limit {
+            /* Records deleted before their expiry are only noticed when it matters */
+            for slug in conn.zrange::<_, Vec<String>>(&key, 0, -1).await? {
+                if !Record::exists(&slug, &mut *conn).await? {
+                    conn.zrem::<_, _, ()>(&key, &slug).await?;
+                }
+            }
+        }
+
+        let active: u64 = conn.zcard(&key).await?;
+        if active >= limit {
+            let soonest: Vec<(String, i64)> = conn.zrange_withscores(&key, 0, 0).await?;
+
+            return Err(Error::TooManyRequests(
+                format!("the quota of {} active records was reached", limit),
+                RateLimit {
+                    limit,
+                    remaining: 0,
+                    reset: soonest
+                        .first()
+                        .map(|(_, expiry)| (expiry - now).max(1) as u64)
+                        .unwrap_or(1),
+                },
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Pool;
+
+    fn policy(bytes: Option<u64>, records: Option<u64>, active: Option<u64>) -> KeyPolicy {
+        KeyPolicy {
+            max_bytes_per_day: bytes,
+            max_records_per_hour: records,
+            max_active_records: active,
+            ..KeyPolicy::default()
+        }
+    }
+
+    #[rocket::async_test]
+    async fn windows_are_enforced() {
+        let (pool, _) = Pool::memory();
+        let mut conn = pool.get();
+        let policy = policy(None, Some(2), None);
+        let throttle = Throttle::new("ip:203.0.113.7", &policy);
+
+        for remaining in [1, 0] {
+            let charge = throttle.check(10, &mut conn).await.unwrap();
+            let limit = charge.limit().unwrap();
+            assert_eq!((limit.limit, limit.remaining), (2, remaining));
+        }
+
+        match throttle.check(10, &mut conn).await {
+            Err(Error::TooManyRequests(_, limit)) => assert_eq!(limit.remaining, 0),
+            _ => panic!("The third record of the hour was allowed"),
+        }
+
+        /* Other subjects have their own quotas */
+        let other = Throttle::new("ip:203.0.113.8", &policy);
+        assert!(other.check(10, &mut conn).await.is_ok());
+    }
+
+    #[rocket::async_test]
+    async fn refused_checks_are_not_counted() {
+        let (pool, _) = Pool::memory();
+        let mut conn = pool.get();
+        let policy = policy(Some(100), Some(10), None);
+        let throttle = Throttle::new("ip:203.0.113.7", &policy);
+
+        assert!(throttle.check(101, &mut conn).await.is_err());
+
+        /* The records window allowed the refused upload, but must not have counted it */
+        let limit = throttle
+            .check(100, &mut conn)
+            .await
+            .unwrap()
+            .limit()
+            .unwrap();
+        assert_eq!((limit.limit, limit.remaining), (100, 0));
+    }
+
+    #[rocket::async_test]
+    async fn refunds_give_back_the_charge() {
+        let (pool, _) = Pool::memory();
+        let mut conn = pool.get();
+        let policy = policy(Some(100), Some(1), None);
+        let throttle = Throttle::new("ip:203.0.113.7", &policy);
+
+        let charge = throttle.check(60, &mut conn).await.unwrap();
+        throttle.refund(&charge, &mut conn).await.unwrap();
+
+        let limit = throttle
+            .check(60, &mut conn)
+            .await
+            .unwrap()
+            .limit()
+            .unwrap();
+        assert_eq!((limit.limit, limit.remaining), (1, 0));
+        assert!(throttle.check(1, &mut conn).await.is_err());
+    }
+
+    #[rocket::async_test]
+    async fn active_records_are_limited() {
+        let (pool, _) = Pool::memory();
+        let mut conn = pool.get();
+        let policy = policy(None, None, Some(1));
+        let throttle = Throttle::new("ip:203.0.113.7", &policy);
+
+        let expiry = Utc::now() + chrono::Duration::hours(1);
+        let record = Record::paste(String::new(), String::from("slug"), None, Some(expiry));
+        record.persist(&mut conn).await.unwrap();
+        throttle.track(&record, &mut conn).await.unwrap();
+
+        match throttle.check(1, &mut conn).await {
+            Err(Error::TooManyRequests(_, limit)) => {
+                assert_eq!((limit.limit, limit.remaining), (1, 0));
+                assert!(limit.reset > 3500 && limit.reset <= 3600);
+            }
+            _ => panic!("The second active record was allowed"),
+        }
+
+        /* Records deleted before their expiry free their place */
+        record.delete(&mut conn).await.unwrap();
+        assert!(throttle.check(1, &mut conn).await.is_ok());
+    }
+
+    #[test]
+    fn tightest_limits_are_relative() {
+        let bytes = RateLimit {
+            limit: 1000,
+            remaining: 100,
+            reset: 1,
+        };
+        let records = RateLimit {
+            limit: 20,
+            remaining: 10,
+            reset: 1,
+        };
+
+        assert!(bytes.is_tighter(&records));
+        assert!(!records.is_tighter(&bytes));
+    }
+}
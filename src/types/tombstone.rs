@@ -0,0 +1,124 @@
+use serde::{Deserialize, Serialize};
+
+use super::{PooledConnection, Result, TOMBSTONE_PREFIX};
+
+/** The reason why a [`Record`](super::Record) is gone */
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    /** The record reached it's expiry date */
+    Expired,
+    /** The record had no accesses left */
+    Exhausted,
+    /** The record was removed to satisfy the storage quota */
+    Evicted,
+    /** The record was deleted by it's owner */
+    Deleted,
+    /** The record was taken down by the operators */
+    TakenDown,
+    /** The record was purged by the operators, without it being a takedown */
+    Purged,
+}
+
+impl std::fmt::Display for Reason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Reason::Expired => "expired",
+            Reason::Exhausted => "exhausted, the download limit was reached",
+            Reason::Evicted => "evicted to free up storage",
+            Reason::Deleted => "deleted by it's owner",
+            Reason::TakenDown => "taken down",
+            Reason::Purged => "removed by the operators",
+        })
+    }
+}
+
+/** Represents the short-lived trace of a dead [`Record`](super::Record), with the reason of it's death */
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Tombstone {
+    reason: Reason,
+    /** Free-form explanation given by the operators, if any */
+    details: Option<String>,
+}
+
+impl Tombstone {
+    /** Instanciate a new [`Tombstone`] from the `reason` */
+    #[inline]
+    pub const fn new(reason: Reason) -> Self {
+        Self {
+            reason,
+            details: None,
+        }
+    }
+
+    /** Attach a free-form explanation of the [`Reason`] to the [`Tombstone`] */
+    #[inline]
+    pub fn with_details(self, details: String) -> Self {
+        Self {
+            details: Some(details),
+            ..self
+        }
+    }
+
+    #[inline]
+    fn key(slug: &str) -> String {
+        [TOMBSTONE_PREFIX, slug].concat()
+    }
+
+    /** Access the [`Tombstone`]'s [`Reason`] */
+    #[inline]
+    pub const fn reason(&self) -> Reason {
+        self.reason
+    }
+
+    /** Access the [`Tombstone`]'s explanation, if any */
+    #[inline]
+    pub fn details(&self) -> Option<&str> {
+        self.details.as_deref()
+    }
+
+    /** Persist the [`Tombstone`] for the `slug` for `lifetime` seconds, or forever if unset, a `lifetime` of 0 disables
+     * it */
+    pub async fn bury(
+        &self,
+        slug: &str,
+        lifetime: Option<u64>,
+        conn: &mut PooledConnection,
+    ) -> Result<()> {
+        if lifetime == Some(0) {
+            return Ok(());
+        }
+
+        let mut cmd = redis::cmd("SET");
+        cmd.arg(Self::key(slug)).arg(bincode::serialize(self)?);
+        if let Some(lifetime) = lifetime {
+            cmd.arg("EX").arg(lifetime);
+        }
+
+        /* An expiry is the least specific reason, so it never overrides another one */
+        if self.reason == Reason::Expired {
+            cmd.arg("NX");
+        }
+
+        Ok(cmd.query_async(conn).await?)
+    }
+
+    /** Whether the `slug` has a [`Tombstone`] on the Redis server */
+    #[inline]
+    pub async fn exists(slug: &str, conn: &mut PooledConnection) -> Result<bool> {
+        use redis::AsyncCommands;
+
+        Ok(conn.exists(Self::key(slug)).await?)
+    }
+
+    /** Pull the [`Tombstone`] of the `slug` from the Redis server, if any */
+    pub async fn fetch(slug: &str, conn: &mut PooledConnection) -> Result<Option<Self>> {
+        use redis::AsyncCommands;
+
+        Ok(conn
+            .get::<_, Option<Vec<u8>>>(Self::key(slug))
+            .await?
+            .map(|tombstone| bincode::deserialize(&tombstone))
+            .transpose()?)
+    }
+}
@@ -0,0 +1,118 @@
+use redis::{
+    cluster::ClusterClient,
+    sentinel::{Sentinel, SentinelNodeConnectionInfo},
+    ConnectionAddr, ConnectionInfo, ErrorKind, IntoConnectionInfo, RedisConnectionInfo, RedisError,
+    Value,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use super::{PooledConnection, Result};
+
+/** The deployment mode of the Redis server */
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RedisMode {
+    /** A single Redis server, reached at `redis_url` */
+    Standalone,
+    /** A master discovered through the Redis Sentinels in `redis_nodes` */
+    Sentinel,
+    /** A Redis Cluster, discovered from the seed nodes in `redis_nodes` */
+    Cluster,
+}
+
+/** The resolved topology of the Redis deployment, from which connections are made */
+#[derive(Clone)]
+pub enum Topology {
+    /** A single Redis server */
+    Standalone(redis::Client),
+    /** The Redis Sentinels electing the named master, which is discovered again after a failover */
+    Sentinel(Arc<Mutex<Sentinel>>, String, SentinelNodeConnectionInfo),
+    /** A Redis Cluster, with the connection informations shared by it's nodes */
+    Cluster(ClusterClient, RedisConnectionInfo),
+}
+
+impl Topology {
+    /** Resolve the [`Topology`] of the Redis deployment from the configuration */
+    pub async fn resolve(config: &crate::Config) -> Result<Self> {
+        let info = config.redis_url.as_str().into_connection_info()?;
+        let nodes = if config.redis_nodes.is_empty() {
+            vec![config.redis_url.clone()]
+        } else {
+            config.redis_nodes.clone()
+        };
+
+        Ok(match config.redis_mode {
+            RedisMode::Standalone => Self::Standalone(redis::Client::open(info)?),
+            RedisMode::Sentinel => Self::Sentinel(
+                Arc::new(Mutex::new(Sentinel::build(nodes)?)),
+                config.redis_master.clone(),
+                SentinelNodeConnectionInfo {
+                    tls_mode: None,
+                    redis_connection_info: Some(info.redis),
+                },
+            ),
+            RedisMode::Cluster => Self::Cluster(ClusterClient::new(nodes)?, info.redis),
+        })
+    }
+
+    /** Retrieve a [`redis::Client`] for the current master of a standalone or Sentinel deployment */
+    pub async fn master(&self) -> redis::RedisResult<redis::Client> {
+        match self {
+            Self::Standalone(client) => Ok(client.clone()),
+            Self::Sentinel(sentinel, name, info) => {
+                let client = sentinel
+                    .lock()
+                    .await
+                    .async_master_for(name, Some(info))
+                    .await?;
+
+                tracing::info!(
+                    "Discovered the `{}` master at {:?} through the Redis Sentinels",
+                    name,
+                    client.get_connection_info().addr
+                );
+
+                Ok(client)
+            }
+            Self::Cluster(..) => Err(RedisError::from((
+                ErrorKind::ClientError,
+                "A Redis Cluster has no single master",
+            ))),
+        }
+    }
+
+    /** Retrieve a [`redis::Client`] for each of the master nodes of the deployment */
+    pub async fn masters(&self, conn: &mut PooledConnection) -> Result<Vec<redis::Client>> {
+        let info = match self {
+            Self::Cluster(_, info) => info,
+            _ => return Ok(vec![self.master().await?]),
+        };
+
+        /* Each slot range is represented as `[start, end, [ip, port, ..], ..replicas]` */
+        let slots: Vec<Vec<Value>> = redis::cmd("CLUSTER").arg("SLOTS").query_async(conn).await?;
+        let mut addresses = slots
+            .into_iter()
+            .filter_map(|range| match range.get(2) {
+                Some(Value::Bulk(master)) => Some(redis::from_redis_value(&Value::Bulk(
+                    master.iter().take(2).cloned().collect(),
+                ))),
+                _ => None,
+            })
+            .collect::<redis::RedisResult<Vec<(String, u16)>>>()?;
+
+        addresses.sort();
+        addresses.dedup();
+
+        Ok(addresses
+            .into_iter()
+            .map(|(host, port)| {
+                redis::Client::open(ConnectionInfo {
+                    addr: ConnectionAddr::Tcp(host, port),
+                    redis: info.clone(),
+                })
+            })
+            .collect::<redis::RedisResult<_>>()?)
+    }
+}
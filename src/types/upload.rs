@@ -0,0 +1,290 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use super::{
+    Error, Identity, PooledConnection, RecordSettings, Result, UPLOAD_LOCK_PREFIX, UPLOAD_PREFIX,
+};
+
+/** Length of the randomly-generated upload identifiers, in characters */
+const ID_LENGTH: usize = 32;
+
+/** Duration after which the lock of an append is given up, should it's holder have died, in milliseconds */
+const LOCK_LEASE: usize = 10 * 60 * 1000;
+
+/** Interval between the renewals of the lock of an append, while it's bytes are being received, in milliseconds */
+const LOCK_RENEWAL: u64 = LOCK_LEASE as u64 / 3;
+
+/** Extend the lock `KEYS[1]` to `ARGV[2]` milliseconds only if it's still held with the token `ARGV[1]` */
+const RENEW_SCRIPT: &str = r"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('PEXPIRE', KEYS[1], ARGV[2])
+end
+return 0
+";
+
+/** Release the lock `KEYS[1]` only if it's still held with the token `ARGV[1]` */
+const UNLOCK_SCRIPT: &str = r"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+";
+
+/** Generate a random alphanumeric string of `length` characters */
+fn random(length: usize) -> String {
+    use rand::{distributions::Alphanumeric, Rng};
+
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect()
+}
+
+/** Represents a resumable upload in progress, with it's parameters */
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Upload {
+    /** [`Upload`]'s random identifier */
+    id: String,
+    /** The name of the file being uploaded */
+    name: String,
+    /** Total length of the upload, in bytes */
+    length: u64,
+    /** Number of bytes received so far */
+    offset: u64,
+    /** Date after which the [`Upload`] will be abandonned */
+    expiry: DateTime<Utc>,
+    /** The [`RecordSettings`] of the creation request, applied upon completion */
+    settings: RecordSettings,
+    /** The [`Identity`] of the creation request, whose limits are applied upon completion */
+    identity: Identity,
+}
+
+impl Upload {
+    /** Instanciate a new [`Upload`] with a random identifier */
+    pub fn new(
+        name: String,
+        length: u64,
+        lifetime: u64,
+        settings: RecordSettings,
+        identity: Identity,
+    ) -> Self {
+        Upload {
+            id: random(ID_LENGTH),
+            name,
+            length,
+            offset: 0,
+            expiry: Utc::now() + Duration::seconds(lifetime as i64),
+            settings,
+            identity,
+        }
+    }
+
+    /** Compute the Redis key of the [`Upload`] identified by `id` */
+    #[inline]
+    pub fn key(id: &str) -> String {
+        [UPLOAD_PREFIX, id].concat()
+    }
+
+    /** Compute the path of the partial file for the [`Upload`] identified by `id` */
+    #[inline]
+    pub fn path(temp: PathBuf, id: &str) -> PathBuf {
+        temp.join([UPLOAD_PREFIX, id].concat())
+    }
+
+    /** Access the [`Upload`]'s `id` */
+    #[inline]
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /** Access the [`Upload`]'s file `name` */
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /** Access the [`Upload`]'s total `length` */
+    #[inline]
+    pub const fn length(&self) -> u64 {
+        self.length
+    }
+
+    /** Access the [`Upload`]'s current `offset` */
+    #[inline]
+    pub const fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /** Access the [`Upload`]'s `expiry` */
+    #[inline]
+    pub const fn expiry(&self) -> DateTime<Utc> {
+        self.expiry
+    }
+
+    /** Access the [`Upload`]'s [`RecordSettings`] */
+    #[inline]
+    pub const fn settings(&self) -> &RecordSettings {
+        &self.settings
+    }
+
+    /** Access the [`Upload`]'s [`Identity`] */
+    #[inline]
+    pub const fn identity(&self) -> &Identity {
+        &self.identity
+    }
+
+    /** Register that `count` more bytes have been received */
+    #[inline]
+    pub fn advance(&mut self, count: u64) {
+        self.offset += count;
+    }
+
+    /** Whether all the bytes of the [`Upload`] have been received */
+    #[inline]
+    pub const fn is_complete(&self) -> bool {
+        self.offset >= self.length
+    }
+
+    /** Persist the [`Upload`] to the Redis server */
+    pub async fn persist(&self, conn: &mut PooledConnection) -> Result<()> {
+        use redis::AsyncCommands;
+
+        conn.set::<_, _, ()>(Self::key(&self.id), bincode::serialize(self)?)
+            .await?;
+        conn.expire_at::<_, ()>(Self::key(&self.id), self.expiry.timestamp() as usize)
+            .await?;
+
+        Ok(())
+    }
+
+    /** Delete the [`Upload`] from the Redis server */
+    #[inline]
+    pub async fn delete(&self, conn: &mut PooledConnection) -> Result<()> {
+        use redis::AsyncCommands;
+
+        Ok(conn.del(Self::key(&self.id)).await?)
+    }
+
+    /** Take the lock of the [`Upload`] identified by `id`, returning it's token unless it's already held */
+    pub async fn lock(id: &str, conn: &mut PooledConnection) -> Result<Option<String>> {
+        let token = random(ID_LENGTH);
+
+        let locked: Option<String> = redis::cmd("SET")
+            .arg([UPLOAD_LOCK_PREFIX, id].concat())
+            .arg(&token)
+            .arg("NX")
+            .arg("PX")
+            .arg(LOCK_LEASE)
+            .query_async(conn)
+            .await?;
+
+        Ok(locked.map(|_| token))
+    }
+
+    /** Extend the lock of the [`Upload`] identified by `id` to a whole lease, returning whether it's still held with
+     * `token` */
+    pub async fn renew(id: &str, token: &str, conn: &mut PooledConnection) -> Result<bool> {
+        Ok(redis::Script::new(RENEW_SCRIPT)
+            .key([UPLOAD_LOCK_PREFIX, id].concat())
+            .arg(token)
+            .arg(LOCK_LEASE)
+            .invoke_async::<_, u8>(conn)
+            .await?
+            == 1)
+    }
+
+    /** Keep renewing the lock of the [`Upload`] identified by `id` for as long as it takes, only returning once it's no
+     * longer held with `token` */
+    pub async fn hold(id: &str, token: &str, mut conn: PooledConnection) -> Error<'static> {
+        let mut renewals = tokio::time::interval(std::time::Duration::from_millis(LOCK_RENEWAL));
+        /* The first tick completes immediately, while the lock was just taken */
+        renewals.tick().await;
+
+        loop {
+            renewals.tick().await;
+
+            match Self::renew(id, token, &mut conn).await {
+                Ok(true) => continue,
+                Ok(false) => return Error::UploadLocked(id.to_string()),
+                Err(err) => return err,
+            }
+        }
+    }
+
+    /** Release the lock of the [`Upload`] identified by `id`, if it's still held with `token` */
+    pub async fn unlock(id: &str, token: &str, conn: &mut PooledConnection) -> Result<()> {
+        redis::Script::new(UNLOCK_SCRIPT)
+            .key([UPLOAD_LOCK_PREFIX, id].concat())
+            .arg(token)
+            .invoke_async::<_, u8>(conn)
+            .await?;
+
+        Ok(())
+    }
+
+    /** Pull an [`Upload`] from the Redis server from it's `id` */
+    pub async fn fetch(id: &str, conn: &mut PooledConnection) -> Result<Option<Self>> {
+        use redis::AsyncCommands;
+
+        Ok(conn
+            .get::<_, Option<Vec<u8>>>(Self::key(id))
+            .await?
+            .map(|upload| bincode::deserialize(&upload))
+            .transpose()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Pool;
+    use std::time::Duration;
+
+    const LEASE: Duration = Duration::from_millis(LOCK_LEASE as u64);
+
+    #[rocket::async_test]
+    async fn locks_are_exclusive() {
+        let (pool, _) = Pool::memory();
+        let mut conn = pool.get();
+
+        let token = Upload::lock("id", &mut conn).await.unwrap().unwrap();
+        assert!(Upload::lock("id", &mut conn).await.unwrap().is_none());
+        assert!(Upload::lock("other", &mut conn).await.unwrap().is_some());
+
+        /* Only the holder may renew or release it */
+        assert!(!Upload::renew("id", "wrong", &mut conn).await.unwrap());
+        Upload::unlock("id", "wrong", &mut conn).await.unwrap();
+        assert!(Upload::lock("id", &mut conn).await.unwrap().is_none());
+
+        Upload::unlock("id", &token, &mut conn).await.unwrap();
+        assert!(Upload::lock("id", &mut conn).await.unwrap().is_some());
+    }
+
+    #[rocket::async_test]
+    async fn renewed_locks_outlive_their_lease() {
+        let (pool, memory) = Pool::memory();
+        let mut conn = pool.get();
+
+        let token = Upload::lock("id", &mut conn).await.unwrap().unwrap();
+        memory.advance(LEASE - Duration::from_secs(1));
+        assert!(Upload::renew("id", &token, &mut conn).await.unwrap());
+
+        memory.advance(LEASE - Duration::from_secs(1));
+        assert!(Upload::lock("id", &mut conn).await.unwrap().is_none());
+        assert!(Upload::renew("id", &token, &mut conn).await.unwrap());
+    }
+
+    #[rocket::async_test]
+    async fn abandonned_locks_expire() {
+        let (pool, memory) = Pool::memory();
+        let mut conn = pool.get();
+
+        let token = Upload::lock("id", &mut conn).await.unwrap().unwrap();
+        memory.advance(LEASE);
+
+        assert!(Upload::lock("id", &mut conn).await.unwrap().is_some());
+        assert!(!Upload::renew("id", &token, &mut conn).await.unwrap());
+    }
+}
@@ -0,0 +1,452 @@
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use super::{
+    target::private_address, PooledConnection, Reason, Record, RecordType, Result, DELIVERIES_KEY,
+    WEBHOOKS_KEY,
+};
+
+/** Base delay before retrying a failed delivery, doubled on every attempt, in seconds */
+const RETRY_DELAY: i64 = 10;
+
+/** A lifecycle event of a [`Record`], as notified to the webhooks */
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+    /** The record has been created */
+    Created,
+    /** The record has been accessed */
+    Accessed,
+    /** The record has been deleted before it's expiry */
+    Deleted,
+    /** The record reached it's expiry date */
+    Expired,
+}
+
+/** The JSON payload POSTed to the webhooks */
+#[derive(Serialize, Debug)]
+struct Notification<'s> {
+    event: Event,
+    slug: &'s str,
+    #[serde(rename = "type")]
+    kind: RecordType,
+    /** Remaining number of accesses, if applicable */
+    accesses: Option<u16>,
+    /** Why the record was deleted, if it was */
+    reason: Option<Reason>,
+    timestamp: i64,
+}
+
+/** The webhook subscription of a [`Record`], outliving it so it's expiry can be notified */
+#[derive(Serialize, Deserialize, Debug)]
+struct Subscription {
+    kind: RecordType,
+    /** The record's own webhook, from the `Notify-Url` header */
+    notify: Option<String>,
+    /** Expiry timestamp of the record, if applicable */
+    expiry: Option<i64>,
+}
+
+/** A pending delivery of a notification, queued in Redis until it succeeds or runs out of attempts */
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Delivery {
+    /** Random identifier, keeping identical deliveries distinct in the queue */
+    id: u64,
+    url: String,
+    payload: String,
+    attempts: u32,
+}
+
+impl Delivery {
+    /** Access the [`Delivery`]'s target `url` */
+    #[inline]
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /** Access the [`Delivery`]'s JSON `payload` */
+    #[inline]
+    pub fn payload(&self) -> &str {
+        &self.payload
+    }
+}
+
+/** Represents the configured webhooks, with the subscriptions and deliveries tracked in Redis */
+#[derive(Debug)]
+pub struct Webhooks {
+    url: Option<String>,
+    secret: Option<String>,
+    retries: u32,
+    /** Whether the records' own urls may point to loopback, private or link-local addresses */
+    allow_private: bool,
+}
+
+impl Webhooks {
+    /** Create a new [`Webhooks`] from it's parameters */
+    #[inline]
+    pub const fn new(
+        url: Option<String>,
+        secret: Option<String>,
+        retries: u32,
+        allow_private: bool,
+    ) -> Self {
+        Self {
+            url,
+            secret,
+            retries,
+            allow_private,
+        }
+    }
+
+    /** Whether a delivery to the `url` is allowed, the records' own urls having to stay out of the private network */
+    pub async fn allows(&self, url: &str) -> bool {
+        if self.allow_private || self.url.as_deref() == Some(url) {
+            return true;
+        }
+
+        match rocket::http::uri::Absolute::parse(url) {
+            Ok(url) => private_address(&url).await.is_none(),
+            Err(_) => false,
+        }
+    }
+
+    /** Compute the `Shrekd-Signature` header value of the `payload`, if a secret is configured */
+    pub fn sign(&self, payload: &str) -> Option<String> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_ref()?.as_bytes()).ok()?;
+        mac.update(payload.as_bytes());
+
+        Some(format!(
+            "sha256={}",
+            hex::encode(mac.finalize().into_bytes())
+        ))
+    }
+
+    /** Subscribe the newly created `record` to the webhooks, with it's own `notify` url, and notify it's creation */
+    pub async fn created(
+        &self,
+        record: &Record,
+        notify: Option<&str>,
+        conn: &mut PooledConnection,
+    ) -> Result<()> {
+        if self.url.is_none() && notify.is_none() {
+            return Ok(());
+        }
+
+        let subscription = Subscription {
+            kind: record.data().kind(),
+            notify: notify.map(String::from),
+            expiry: record.expiry().map(|expiry| expiry.timestamp()),
+        };
+
+        conn.hset::<_, _, _, ()>(
+            WEBHOOKS_KEY,
+            record.slug(),
+            bincode::serialize(&subscription)?,
+        )
+        .await?;
+
+        self.enqueue(
+            record.slug(),
+            &subscription,
+            Event::Created,
+            record.accesses(),
+            None,
+            conn,
+        )
+        .await
+    }
+
+    /** Notify an access to the `slug`'s record, with it's remaining `accesses` */
+    pub async fn accessed(
+        &self,
+        slug: &str,
+        accesses: Option<u16>,
+        conn: &mut PooledConnection,
+    ) -> Result<()> {
+        let subscription = match conn
+            .hget::<_, _, Option<Vec<u8>>>(WEBHOOKS_KEY, slug)
+            .await?
+        {
+            Some(subscription) => bincode::deserialize(&subscription)?,
+            None => return Ok(()),
+        };
+
+        self.enqueue(slug, &subscription, Event::Accessed, accesses, None, conn)
+            .await
+    }
+
+    /** Notify the deletion of the `slug`'s record for the `reason`, and drop it's subscription */
+    pub async fn deleted(
+        &self,
+        slug: &str,
+        reason: Reason,
+        conn: &mut PooledConnection,
+    ) -> Result<()> {
+        match self.unsubscribe(slug, &mut *conn).await? {
+            Some(subscription) => {
+                self.enqueue(
+                    slug,
+                    &subscription,
+                    Event::Deleted,
+                    None,
+                    Some(reason),
+                    conn,
+                )
+                .await
+            }
+            None => Ok(()),
+        }
+    }
+
+    /** Notify the expiry of the `slug`'s record, and drop it's subscription */
+    pub async fn expired(&self, slug: &str, conn: &mut PooledConnection) -> Result<()> {
+        match self.unsubscribe(slug, &mut *conn).await? {
+            Some(subscription) => {
+                self.enqueue(slug, &subscription, Event::Expired, None, None, conn)
+                    .await
+            }
+            None => Ok(()),
+        }
+    }
+
+    /** List the slugs subscribed to the webhooks with their expiry timestamp, if applicable */
+    pub async fn subscriptions(conn: &mut PooledConnection) -> Result<Vec<(String, Option<i64>)>> {
+        let subscriptions: Vec<(String, Vec<u8>)> = conn.hgetall(WEBHOOKS_KEY).await?;
+
+        subscriptions
+            .into_iter()
+            .map(|(slug, subscription)| {
+                let subscription: Subscription = bincode::deserialize(&subscription)?;
+
+                Ok((slug, subscription.expiry))
+            })
+            .collect()
+    }
+
+    /** Atomically take the `slug`'s [`Subscription`] out of the Redis server, so it's only notified once */
+    async fn unsubscribe(
+        &self,
+        slug: &str,
+        conn: &mut PooledConnection,
+    ) -> Result<Option<Subscription>> {
+        let (subscription, _): (Option<Vec<u8>>, u8) = redis::pipe()
+            .atomic()
+            .hget(WEBHOOKS_KEY, slug)
+            .hdel(WEBHOOKS_KEY, slug)
+            .query_async(conn)
+            .await?;
+
+        Ok(subscription
+            .map(|subscription| bincode::deserialize(&subscription))
+            .transpose()?)
+    }
+
+    /** Queue a delivery of the notification to each of the [`Subscription`]'s webhooks */
+    async fn enqueue(
+        &self,
+        slug: &str,
+        subscription: &Subscription,
+        event: Event,
+        accesses: Option<u16>,
+        reason: Option<Reason>,
+        conn: &mut PooledConnection,
+    ) -> Result<()> {
+        let payload = rocket::serde::json::serde_json::to_string(&Notification {
+            event,
+            slug,
+            kind: subscription.kind,
+            accesses,
+            reason,
+            timestamp: Utc::now().timestamp(),
+        })
+        .map_err(|err| crate::Error::Intrinsics(err.to_string().into()))?;
+
+        for url in self.url.iter().chain(subscription.notify.iter()) {
+            self.schedule(
+                &Delivery {
+                    id: rand::random(),
+                    url: url.clone(),
+                    payload: payload.clone(),
+                    attempts: 0,
+                },
+                Utc::now().timestamp(),
+                conn,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    async fn schedule(
+        &self,
+        delivery: &Delivery,
+        timestamp: i64,
+        conn: &mut PooledConnection,
+    ) -> Result<()> {
+        Ok(conn
+            .zadd(DELIVERIES_KEY, bincode::serialize(delivery)?, timestamp)
+            .await?)
+    }
+
+    /** Take the deliveries that are due out of the queue, so no other instance attempts them */
+    pub async fn due(conn: &mut PooledConnection) -> Result<Vec<Delivery>> {
+        let deliveries: Vec<Vec<u8>> = conn
+            .zrangebyscore_limit(DELIVERIES_KEY, "-inf", Utc::now().timestamp(), 0, 16)
+            .await?;
+
+        let mut taken = Vec::with_capacity(deliveries.len());
+        for delivery in deliveries {
+            if conn.zrem::<_, _, u8>(DELIVERIES_KEY, &delivery).await? == 1 {
+                taken.push(bincode::deserialize(&delivery)?);
+            }
+        }
+
+        Ok(taken)
+    }
+
+    /** Queue the failed `delivery` again with an exponential backoff, unless it ran out of attempts */
+    pub async fn retry(&self, mut delivery: Delivery, conn: &mut PooledConnection) -> Result<()> {
+        delivery.attempts += 1;
+
+        if delivery.attempts > self.retries {
+            tracing::warn!(
+                "Giving up on the delivery to `{}` after {} attempts",
+                delivery.url,
+                delivery.attempts
+            );
+
+            return Ok(());
+        }
+
+        let delay = RETRY_DELAY << (delivery.attempts - 1).min(16);
+        self.schedule(&delivery, Utc::now().timestamp() + delay, conn)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::serde::json::{self, Value};
+
+    use super::*;
+    use crate::types::Pool;
+
+    fn events(deliveries: &[Delivery]) -> Vec<(String, String)> {
+        deliveries
+            .iter()
+            .map(|delivery| {
+                let payload: Value = json::from_str(delivery.payload()).unwrap();
+
+                (
+                    delivery.url().into(),
+                    payload["event"].as_str().unwrap().into(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn payloads_are_signed_with_the_secret() {
+        let webhooks = Webhooks::new(None, Some(String::from("key")), 0, false);
+
+        assert_eq!(
+            webhooks
+                .sign("The quick brown fox jumps over the lazy dog")
+                .unwrap(),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+        assert!(Webhooks::new(None, None, 0, false).sign("{}").is_none());
+    }
+
+    #[rocket::async_test]
+    async fn private_urls_are_refused() {
+        let webhooks = Webhooks::new(Some(String::from("http://127.0.0.1/hook")), None, 0, false);
+
+        assert!(webhooks.allows("http://127.0.0.1/hook").await);
+        assert!(!webhooks.allows("http://127.0.0.1/other").await);
+        assert!(!webhooks.allows("http://[::1]/hook").await);
+        assert!(
+            Webhooks::new(None, None, 0, true)
+                .allows("http://127.0.0.1/other")
+                .await
+        );
+    }
+
+    #[rocket::async_test]
+    async fn lifecycles_are_notified_once() {
+        let (pool, _) = Pool::memory();
+        let mut conn = pool.get();
+        let webhooks = Webhooks::new(Some(String::from("https://hooks.example")), None, 0, false);
+        let record = Record::paste(String::from("content"), String::from("slug"), None, None);
+
+        webhooks
+            .created(&record, Some("https://notify.example"), &mut conn)
+            .await
+            .unwrap();
+        webhooks.accessed("slug", None, &mut conn).await.unwrap();
+        assert_eq!(events(&Webhooks::due(&mut conn).await.unwrap()).len(), 4);
+
+        for _ in 0..2 {
+            webhooks.expired("slug", &mut conn).await.unwrap();
+        }
+        let mut expired = events(&Webhooks::due(&mut conn).await.unwrap());
+        expired.sort();
+        assert_eq!(
+            expired,
+            [
+                (
+                    String::from("https://hooks.example"),
+                    String::from("expired")
+                ),
+                (
+                    String::from("https://notify.example"),
+                    String::from("expired")
+                ),
+            ]
+        );
+        assert!(Webhooks::subscriptions(&mut conn).await.unwrap().is_empty());
+
+        /* Records created without any webhook are never subscribed */
+        Webhooks::new(None, None, 0, false)
+            .created(&record, None, &mut conn)
+            .await
+            .unwrap();
+        assert!(Webhooks::subscriptions(&mut conn).await.unwrap().is_empty());
+    }
+
+    #[rocket::async_test]
+    async fn failed_deliveries_are_retried_with_a_limit() {
+        let (pool, _) = Pool::memory();
+        let mut conn = pool.get();
+        let webhooks = Webhooks::new(Some(String::from("https://hooks.example")), None, 1, false);
+        let record = Record::paste(String::from("content"), String::from("slug"), None, None);
+
+        webhooks.created(&record, None, &mut conn).await.unwrap();
+        let delivery = Webhooks::due(&mut conn).await.unwrap().pop().unwrap();
+        assert!(Webhooks::due(&mut conn).await.unwrap().is_empty());
+
+        /* The retry is delayed, and the next failure is the last */
+        webhooks.retry(delivery, &mut conn).await.unwrap();
+        assert!(Webhooks::due(&mut conn).await.unwrap().is_empty());
+        let delivery = conn
+            .zrange::<_, Vec<Vec<u8>>>(DELIVERIES_KEY, 0, 0)
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        conn.zrem::<_, _, ()>(DELIVERIES_KEY, &delivery)
+            .await
+            .unwrap();
+
+        webhooks
+            .retry(bincode::deserialize(&delivery).unwrap(), &mut conn)
+            .await
+            .unwrap();
+        assert_eq!(conn.zcard::<_, u64>(DELIVERIES_KEY).await.unwrap(), 0);
+    }
+}
@@ -1,4 +1,7 @@
-use crate::{types::HostBase, Config};
+use crate::{
+    types::{HostBase, Pool, Quota, Shape},
+    Config, Error,
+};
 use rocket::{
     data::ByteUnit,
     fs::FileServer,
@@ -8,10 +11,17 @@ use rocket::{
 };
 
 #[get("/")]
-fn index<'r>(
+async fn index<'r>(
     config: &State<Config>,
-    host: HostBase<'_>,
+    redis: &State<Pool>,
+    host: Result<HostBase<'_>, Error<'static>>,
 ) -> crate::Result<impl Responder<'r, 'static>> {
+    let host = host?;
+    let mut conn = redis.get();
+    let usage = Quota::usage(&mut conn).await?;
+    let curve = config.curve()?;
+    let paste_curve = config.paste_curve()?;
+
     let template = liquid::ParserBuilder::with_stdlib()
         .build()?
         .parse(include_str!("../../ui/index.html"))?;
@@ -21,6 +31,28 @@ fn index<'r>(
             "file": ByteUnit::from(config.max_file_size).to_string(),
             "paste": ByteUnit::from(config.max_paste_size).to_string(),
             "url": ByteUnit::from(config.max_url_size).to_string(),
+            "storage": {
+                "usage": ByteUnit::from(usage).to_string(),
+                "quota": config
+                    .quota()
+                    .limit()
+                    .map(|limit| ByteUnit::from(limit).to_string())
+                    .unwrap_or_else(|| String::from("unlimited")),
+            },
+        },
+        "retention": {
+            "min": Shape::format_duration(curve.min()),
+            "max": Shape::format_duration(curve.max()),
+            "shape": curve.shape().to_string(),
+            "paste": {
+                "min": Shape::format_duration(paste_curve.min()),
+                "max": Shape::format_duration(paste_curve.max()),
+                "shape": paste_curve.shape().to_string(),
+            },
+            "url": {
+                "default": Shape::format_duration(config.url_default_age),
+                "max": Shape::format_duration(config.url_max_age),
+            },
         },
         "base": host.into_inner(),
         "version": env!("CARGO_PKG_VERSION")
@@ -0,0 +1,86 @@
+/*!
+ * Delivery of the webhook notifications, drained from their Redis-backed queue and retried on failure
+ */
+use std::time::Duration;
+
+use crate::{
+    types::{Delivery, PooledConnection, Webhooks},
+    Config, Result,
+};
+
+/** Interval between the polls of the delivery queue, in seconds */
+const POLL_INTERVAL: u64 = 1;
+/** Timeout of a single delivery, in seconds */
+const DELIVERY_TIMEOUT: u64 = 10;
+
+/** Run the delivery of the queued notifications forever */
+pub async fn run(config: Config, mut conn: PooledConnection) {
+    let webhooks = config.webhooks();
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(DELIVERY_TIMEOUT))
+        .user_agent(concat!("shrekd/", env!("CARGO_PKG_VERSION")))
+        /* A redirect would lead the delivery anywhere, past the checks of it's url */
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .expect("Failed to build the webhooks HTTP client");
+
+    let mut interval = tokio::time::interval(Duration::from_secs(POLL_INTERVAL));
+
+    loop {
+        interval.tick().await;
+
+        if let Err(err) = drain(&webhooks, &client, &mut conn).await {
+            tracing::error!("Failed to deliver the queued notifications: {}", err);
+        }
+    }
+}
+
+/** Attempt every delivery that is due, queueing the failed ones for a retry */
+async fn drain(
+    webhooks: &Webhooks,
+    client: &reqwest::Client,
+    conn: &mut PooledConnection,
+) -> Result<()> {
+    loop {
+        let deliveries = Webhooks::due(&mut *conn).await?;
+        if deliveries.is_empty() {
+            return Ok(());
+        }
+
+        for delivery in deliveries {
+            /* The records' urls are checked again, since their host may resolve elsewhere by now */
+            if !webhooks.allows(delivery.url()).await {
+                tracing::warn!("Refused to deliver to the private `{}`", delivery.url());
+                continue;
+            }
+
+            if let Err(err) = deliver(webhooks, client, &delivery).await {
+                tracing::warn!("Failed to deliver to `{}`: {}", delivery.url(), err);
+
+                webhooks.retry(delivery, &mut *conn).await?;
+            }
+        }
+    }
+}
+
+/** POST the [`Delivery`]'s payload to it's url, signing it if possible */
+async fn deliver(
+    webhooks: &Webhooks,
+    client: &reqwest::Client,
+    delivery: &Delivery,
+) -> reqwest::Result<()> {
+    let mut request = client
+        .post(delivery.url())
+        .header("Content-Type", "application/json")
+        .body(delivery.payload().to_string());
+
+    if let Some(signature) = webhooks.sign(delivery.payload()) {
+        request = request.header("Shrekd-Signature", signature);
+    }
+
+    request.send().await?.error_for_status()?;
+
+    tracing::debug!("Delivered a notification to `{}`", delivery.url());
+
+    Ok(())
+}
@@ -1,9 +1,31 @@
 $(() => {
 
+    /* Show the QR code of the created record's link, or hide it on failure, keeping the signature of signed links */
+    function showQr(id, link) {
+        if (link) {
+            let qr = new URL(link);
+            qr.pathname += "/qr";
+            qr.searchParams.set("size", 128);
+
+            $(`#${id}-qr`).attr("src", qr.toString()).show();
+        } else {
+            $(`#${id}-qr`).hide();
+        }
+    }
+
     $("#file-upload-input").change(function (event) {
         let file = $(this).prop("files")[0];
 
         $("#file-upload-output-outer").hide();
+        $("#file-retention-output-outer").hide();
+
+        $.getJSON(`/api/retention?size=${file.size}&type=file`)
+        .then((data) => {
+            let days = Math.max(1, Math.round(data.retention / 86400));
+
+            $("#file-retention-output").text(`This file will be kept for ${days} day${days > 1 ? "s" : ""}`);
+            $("#file-retention-output-outer").show();
+        });
 
         $.ajax({
             url: `/${encodeURIComponent(file.name)}`,
@@ -23,6 +45,7 @@ $(() => {
             }
             $("#file-upload-output").removeClass("status-ok");
             $("#file-upload-output").addClass("status-ko");
+            showQr("file-upload");
             $("#file-upload-output-outer").show();
         })
         .then((data, _, req) => {
@@ -31,6 +54,7 @@ $(() => {
             $("#file-upload-output").text(data);
             $("#file-upload-output").removeClass("status-ko");
             $("#file-upload-output").addClass("status-ok");
+            showQr("file-upload", data);
             $("#file-upload-output-outer").show();
         });
     });
@@ -63,6 +87,7 @@ $(() => {
             }
             $("#paste-creation-output").removeClass("status-ok");
             $("#paste-creation-output").addClass("status-ko");
+            showQr("paste-creation");
             $("#paste-creation-output-outer").show();
         })
         .then((data, _, req) => {
@@ -71,6 +96,7 @@ $(() => {
             $("#paste-creation-output").text(data);
             $("#paste-creation-output").removeClass("status-ko");
             $("#paste-creation-output").addClass("status-ok");
+            showQr("paste-creation", data);
             $("#paste-creation-output-outer").show();
         });
     });
@@ -103,6 +129,7 @@ $(() => {
             }
             $("#redirect-creation-output").removeClass("status-ok");
             $("#redirect-creation-output").addClass("status-ko");
+            showQr("redirect-creation");
             $("#redirect-creation-output-outer").show();
         })
         .then((data, _, req) => {
@@ -111,6 +138,7 @@ $(() => {
             $("#redirect-creation-output").text(data);
             $("#redirect-creation-output").removeClass("status-ko");
             $("#redirect-creation-output").addClass("status-ok");
+            showQr("redirect-creation", data);
             $("#redirect-creation-output-outer").show();
         });
     });